@@ -0,0 +1,258 @@
+//! # Acks
+//!
+//! Acknowledges the receipt of messages. A sender asks for an ack with the `please_ack` header, the recipient answers with
+//! a message listing the acknowledged ids in its `ack` header, either an explicit ack or any other message of the thread.
+//! <https://identity.foundation/didcomm-messaging/spec/#acks>
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0015-acks/README.md>
+
+use crate::connection::Connection;
+use crate::headers;
+use chrono::{DateTime, Duration, Utc};
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AckStatus {
+    Ok,
+    /// The message was received but is not processed yet.
+    Pending,
+    Fail,
+}
+
+/// A received acknowledgement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub id: String,
+    pub thid: Option<String>,
+    pub status: AckStatus,
+    /// ids of the acknowledged messages
+    pub ack: Vec<String>,
+}
+
+impl Ack {
+    /// True if the ack is for the message, by its ack header or else by its thread.
+    pub fn acknowledges(&self, message: &Message) -> bool {
+        let header = message.get_didcomm_header();
+        if !self.ack.is_empty() {
+            return self.ack.contains(&header.id);
+        }
+        self.thid.as_ref() == Some(&header.id) || self.thid.is_some() && self.thid == header.thid
+    }
+}
+
+/// Reads explicit acks, problem reports acknowledge with status fail and other messages with an `ack` header with ok.
+impl TryFrom<&Message> for Ack {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        let ack = headers::get_list(message, "ack");
+        let status = match header.m_type.as_str() {
+            "https://didcomm.org/notification/1.0/ack" => {
+                let body: Value =
+                    serde_json::from_str(&message.get_body().map_err(|_| "invalid body")?)
+                        .map_err(|_| "invalid body")?;
+                serde_json::from_value(body["status"].clone()).unwrap_or(AckStatus::Ok)
+            }
+            "https://didcomm.org/report-problem/2.0/problem-report" if !ack.is_empty() => {
+                AckStatus::Fail
+            }
+            _ if !ack.is_empty() => AckStatus::Ok,
+            _ => return Err("no ack"),
+        };
+        Ok(Ack {
+            id: header.id.clone(),
+            thid: header.thid.clone(),
+            status,
+            ack,
+        })
+    }
+}
+
+pub struct AckBuilder {
+    message: Option<Message>,
+    status: AckStatus,
+    connection: Option<Connection>,
+}
+
+impl Default for AckBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AckBuilder {
+    pub fn new() -> Self {
+        AckBuilder {
+            message: None,
+            status: AckStatus::Ok,
+            connection: None,
+        }
+    }
+
+    pub fn message(&mut self, message: Message) -> &mut Self {
+        self.message = Some(message);
+        self
+    }
+
+    pub fn status(&mut self, status: AckStatus) -> &mut Self {
+        self.status = status;
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    /// Acknowledges the ids the message asks for in `please_ack` or else the message itself.
+    pub fn build(&mut self) -> Result<Message, &'static str> {
+        let acked = self.message.as_ref().ok_or("no message")?;
+        let header = acked.get_didcomm_header();
+        let mut ack = headers::get_list(acked, "please_ack");
+        if ack.is_empty() {
+            ack.push(header.id.clone());
+        }
+        let mut message = Message::new()
+            .m_type("https://didcomm.org/notification/1.0/ack")
+            .thid(header.thid.as_ref().unwrap_or(&header.id))
+            .body(&json!({ "status": self.status }).to_string());
+        message = headers::add_list(message, "ack", &ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+/// Matches received acks with the sent messages and finds messages without ack.
+#[derive(Default)]
+pub struct AckTracker {
+    pending: HashMap<String, (Message, DateTime<Utc>)>,
+}
+
+impl AckTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&mut self, message: &Message) -> &mut Self {
+        self.track_at(message, Utc::now())
+    }
+
+    pub fn track_at(&mut self, message: &Message, sent_time: DateTime<Utc>) -> &mut Self {
+        self.pending.insert(
+            message.get_didcomm_header().id.clone(),
+            (message.clone(), sent_time),
+        );
+        self
+    }
+
+    /// Returns the ids of the sent messages the ack is for, a pending ack keeps them tracked.
+    pub fn receive(&mut self, message: &Message) -> Result<(Ack, Vec<String>), &'static str> {
+        let ack = Ack::try_from(message)?;
+        let ids: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, (sent, _))| ack.acknowledges(sent))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        if ack.status != AckStatus::Pending {
+            for id in &ids {
+                self.pending.remove(id);
+            }
+        }
+        Ok((ack, ids))
+    }
+
+    pub fn is_pending(&self, id: &str) -> bool {
+        self.pending.contains_key(id)
+    }
+
+    /// Ids of messages without ack for longer than the timeout.
+    pub fn timed_out(&self, timeout: Duration) -> Vec<String> {
+        self.timed_out_at(timeout, Utc::now())
+    }
+
+    pub fn timed_out_at(&self, timeout: Duration, now: DateTime<Utc>) -> Vec<String> {
+        self.pending
+            .iter()
+            .filter(|(_, (_, sent_time))| now - *sent_time > timeout)
+            .map(|(id, _)| id.to_string())
+            .collect()
+    }
+
+    pub fn forget(&mut self, id: &str) -> Option<Message> {
+        self.pending.remove(id).map(|(message, _)| message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BasicMessageBuilder, IssueCredentialResponseBuilder, ProtocolError,
+        ReportProblemResponseBuilder,
+    };
+
+    #[test]
+    fn test_ack_credential() {
+        let now = Utc::now();
+        let credential = IssueCredentialResponseBuilder::new()
+            .attachment(Value::String("Credential".to_string()))
+            .please_ack(true)
+            .build_issue_credential()
+            .unwrap();
+        let id = credential.get_didcomm_header().id.to_string();
+        let mut tracker = AckTracker::new();
+        tracker.track_at(&credential, now);
+
+        let pending = AckBuilder::new()
+            .message(credential.clone())
+            .status(AckStatus::Pending)
+            .build()
+            .unwrap();
+        let (ack, ids) = tracker.receive(&pending).unwrap();
+        assert_eq!(ack.status, AckStatus::Pending);
+        assert_eq!(ids, vec![id.to_string()]);
+        assert!(tracker.is_pending(&id));
+        assert_eq!(
+            tracker.timed_out_at(Duration::seconds(10), now + Duration::seconds(11)),
+            vec![id.to_string()]
+        );
+
+        let ack = AckBuilder::new().message(credential).build().unwrap();
+        assert_eq!(headers::get_list(&ack, "ack"), vec![id.to_string()]);
+        let (ack, ids) = tracker.receive(&ack).unwrap();
+        assert_eq!(ack.status, AckStatus::Ok);
+        assert_eq!(ids, vec![id.to_string()]);
+        assert!(!tracker.is_pending(&id));
+    }
+
+    #[test]
+    fn test_implicit_acks() {
+        let message = BasicMessageBuilder::new()
+            .message("Hello World".to_string())
+            .please_ack(true)
+            .build()
+            .unwrap();
+        let mut tracker = AckTracker::new();
+        tracker.track(&message);
+
+        let report = ProtocolError::Other("too busy".to_string())
+            .report(&message)
+            .unwrap();
+        let (ack, ids) = tracker.receive(&report).unwrap();
+        assert_eq!(ack.status, AckStatus::Fail);
+        assert_eq!(ids, vec![message.get_didcomm_header().id.to_string()]);
+
+        let report = ReportProblemResponseBuilder::new()
+            .code("e.p.me".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(tracker.receive(&report).unwrap_err(), "no ack");
+    }
+}
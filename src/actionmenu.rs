@@ -0,0 +1,475 @@
+//! # Action Menu
+//!
+//! A responder presents a menu of actions to the requester, like `Check balance` or `Request statement`. The requester
+//! asks for the menu with a menu-request and selects an option with a perform message, filling in the form of the
+//! option if it has one. The responder answers a perform with any message of its choice or a new menu.
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0509-action-menu/README.md>
+
+use crate::connection::Connection;
+use crate::headers;
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const MENU: &str = "https://didcomm.org/action-menu/1.0/menu";
+pub const MENU_REQUEST: &str = "https://didcomm.org/action-menu/1.0/menu-request";
+pub const PERFORM: &str = "https://didcomm.org/action-menu/1.0/perform";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Menu {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// error of the previous perform, shown with the menu
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errormsg: Option<String>,
+    pub options: Vec<MenuOption>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MenuOption {
+    /// unique name the option is performed by
+    pub name: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub form: Option<Form>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Form {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default)]
+    pub params: Vec<FormParam>,
+    #[serde(rename = "submit-label", skip_serializing_if = "Option::is_none")]
+    pub submit_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FormParam {
+    pub name: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    /// input type like `text`, defaults to text
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_: Option<String>,
+}
+
+/// A selected option of a menu with the values of its form.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Perform {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub params: HashMap<String, String>,
+    /// thread of the menu
+    #[serde(skip)]
+    pub thid: Option<String>,
+}
+
+impl Menu {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        for (index, option) in self.options.iter().enumerate() {
+            if option.name.is_empty() {
+                return Err("missing option name");
+            }
+            if self.options[..index]
+                .iter()
+                .any(|other| other.name == option.name)
+            {
+                return Err("duplicate option name");
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the perform selects an enabled option of the menu and has the required parameters. Missing parameters
+    /// with a default are filled in with it.
+    pub fn check(&self, perform: &Perform) -> Result<Perform, &'static str> {
+        let option = self
+            .options
+            .iter()
+            .find(|option| option.name == perform.name)
+            .ok_or("unknown option")?;
+        if option.disabled {
+            return Err("option disabled");
+        }
+        let mut perform = perform.clone();
+        for param in option.form.iter().flat_map(|form| form.params.iter()) {
+            if perform.params.contains_key(&param.name) {
+                continue;
+            }
+            match param.default.as_ref() {
+                Some(default) => {
+                    perform
+                        .params
+                        .insert(param.name.to_string(), default.to_string());
+                }
+                None if param.required => return Err("missing parameter"),
+                None => {}
+            }
+        }
+        Ok(perform)
+    }
+}
+
+impl TryFrom<&Message> for Menu {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        if message.get_didcomm_header().m_type != MENU {
+            return Err("unsupported message");
+        }
+        let body = message.get_body().map_err(|_| "invalid body")?;
+        let menu: Menu = serde_json::from_str(&body).map_err(|_| "invalid body")?;
+        menu.validate()?;
+        Ok(menu)
+    }
+}
+
+impl TryFrom<&Message> for Perform {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if header.m_type != PERFORM {
+            return Err("unsupported message");
+        }
+        let body = message.get_body().map_err(|_| "invalid body")?;
+        let mut perform: Perform = serde_json::from_str(&body).map_err(|_| "invalid body")?;
+        perform.thid = header.thid.clone();
+        Ok(perform)
+    }
+}
+
+/// Action Menu Builder
+#[derive(Default)]
+pub struct ActionMenuBuilder {
+    menu: Option<Menu>,
+    message: Option<Message>,
+    perform: Option<(String, HashMap<String, String>)>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+}
+
+impl ActionMenuBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn menu(&mut self, menu: Menu) -> &mut Self {
+        self.menu = Some(menu);
+        self
+    }
+
+    /// Message to respond to, a menu-request or the menu to perform an option of.
+    pub fn message(&mut self, message: Message) -> &mut Self {
+        self.message = Some(message);
+        self
+    }
+
+    /// Option to perform with the values of its form.
+    pub fn perform(&mut self, name: &str, params: HashMap<String, String>) -> &mut Self {
+        self.perform = Some((name.to_string(), params));
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build_menu(&mut self) -> Result<Message, &'static str> {
+        let menu = self.menu.as_ref().ok_or("no menu")?;
+        menu.validate()?;
+        self.build_message(MENU, json!(menu))
+    }
+
+    pub fn build_menu_request(&mut self) -> Result<Message, &'static str> {
+        self.build_message(MENU_REQUEST, json!({}))
+    }
+
+    /// Builds the perform, checked against the menu it responds to.
+    pub fn build_perform(&mut self) -> Result<Message, &'static str> {
+        let (name, params) = self.perform.clone().ok_or("no option")?;
+        let mut perform = Perform {
+            name,
+            params,
+            thid: None,
+        };
+        if let Some(message) = self.message.as_ref() {
+            perform = Menu::try_from(message)?.check(&perform)?;
+        }
+        self.build_message(PERFORM, json!(perform))
+    }
+
+    fn build_message(&self, m_type: &str, body: Value) -> Result<Message, &'static str> {
+        let mut message = Message::new().m_type(m_type).body(&body.to_string());
+        if let Some(received) = self.message.as_ref() {
+            let header = received.get_didcomm_header();
+            message = message.thid(header.thid.as_ref().unwrap_or(&header.id));
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+/// Performs a menu option, implemented for closures taking a [`Perform`]. Returns the response to the requester or
+/// `None` to present the menu again.
+pub trait PerformHandler {
+    fn perform(&mut self, perform: &Perform) -> Result<Option<Message>, &'static str>;
+}
+
+impl<F> PerformHandler for F
+where
+    F: FnMut(&Perform) -> Result<Option<Message>, &'static str>,
+{
+    fn perform(&mut self, perform: &Perform) -> Result<Option<Message>, &'static str> {
+        self(perform)
+    }
+}
+
+/// Responder side of the protocol, answers menu-requests with the menu and passes performs to the handlers of
+/// their options. A perform that fails is answered with the menu and the error as `errormsg`. Responses of handlers
+/// are sent in the thread of the perform, over the connection of the server.
+pub struct ActionMenuServer {
+    menu: Menu,
+    handlers: HashMap<String, Box<dyn PerformHandler>>,
+    connection: Option<Connection>,
+}
+
+impl ActionMenuServer {
+    pub fn new(menu: Menu) -> Self {
+        ActionMenuServer {
+            menu,
+            handlers: HashMap::new(),
+            connection: None,
+        }
+    }
+
+    /// Handles performs of the option with the name.
+    pub fn handler<H: PerformHandler + 'static>(&mut self, name: &str, handler: H) -> &mut Self {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn handle(&mut self, message: &Message) -> Result<Message, &'static str> {
+        match message.get_didcomm_header().m_type.as_str() {
+            MENU_REQUEST => self.respond_menu(message, None),
+            PERFORM => {
+                let result = Perform::try_from(message)
+                    .and_then(|perform| self.menu.check(&perform))
+                    .and_then(|perform| {
+                        self.handlers
+                            .get_mut(&perform.name)
+                            .ok_or("unknown option")?
+                            .perform(&perform)
+                    });
+                match result {
+                    Ok(Some(response)) => Ok(self.reply(message, response)),
+                    Ok(None) => self.respond_menu(message, None),
+                    Err(error) => self.respond_menu(message, Some(error)),
+                }
+            }
+            _ => Err("unsupported message"),
+        }
+    }
+
+    fn reply(&self, perform: &Message, mut response: Message) -> Message {
+        let header = perform.get_didcomm_header();
+        response = response.thid(header.thid.as_ref().unwrap_or(&header.id));
+        if let Some(connection) = self.connection.as_ref() {
+            response = connection.address(response);
+        }
+        response
+    }
+
+    fn respond_menu(
+        &self,
+        message: &Message,
+        errormsg: Option<&str>,
+    ) -> Result<Message, &'static str> {
+        let mut menu = self.menu.clone();
+        menu.errormsg = errormsg.map(String::from);
+        let mut builder = ActionMenuBuilder::new();
+        builder.menu(menu).message(message.clone());
+        if let Some(connection) = self.connection.as_ref() {
+            builder.connection(connection.clone());
+        }
+        builder.build_menu()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ConnectionState;
+    use crate::BasicMessageBuilder;
+
+    fn menu() -> Menu {
+        serde_json::from_value(json!({
+            "title": "Welcome to IIWBook",
+            "description": "IIWBook facilitates connections between attendees by verifying attendance and distributing connection invitations.",
+            "options": [
+                { "name": "check-balance", "title": "Check balance" },
+                {
+                    "name": "request-statement",
+                    "title": "Request statement",
+                    "form": {
+                        "title": "Statement",
+                        "params": [
+                            { "name": "month", "title": "Month", "required": true },
+                            { "name": "format", "title": "Format", "default": "pdf" }
+                        ],
+                        "submit-label": "Request"
+                    }
+                },
+                { "name": "close-account", "title": "Close account", "disabled": true }
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_perform_option() {
+        let request = ActionMenuBuilder::new().build_menu_request().unwrap();
+        let menu_message = ActionMenuBuilder::new()
+            .menu(menu())
+            .message(request)
+            .build_menu()
+            .unwrap();
+        let received = Menu::try_from(&menu_message).unwrap();
+        assert_eq!(
+            received.options[1].form.as_ref().unwrap().submit_label,
+            Some("Request".to_string())
+        );
+
+        let params = HashMap::from([("month".to_string(), "2024-01".to_string())]);
+        let perform = ActionMenuBuilder::new()
+            .message(menu_message.clone())
+            .perform("request-statement", params)
+            .build_perform()
+            .unwrap();
+        let perform = Perform::try_from(&perform).unwrap();
+        assert_eq!(perform.params["format"], "pdf");
+        assert_eq!(perform.thid, menu_message.get_didcomm_header().thid);
+
+        assert_eq!(
+            ActionMenuBuilder::new()
+                .message(menu_message.clone())
+                .perform("request-statement", HashMap::new())
+                .build_perform()
+                .unwrap_err(),
+            "missing parameter"
+        );
+        assert_eq!(
+            ActionMenuBuilder::new()
+                .message(menu_message)
+                .perform("close-account", HashMap::new())
+                .build_perform()
+                .unwrap_err(),
+            "option disabled"
+        );
+    }
+
+    #[test]
+    fn test_menu_server() {
+        let mut server = ActionMenuServer::new(menu());
+        server
+            .connection(Connection {
+                id: "42".to_string(),
+                did: "did:key:bank".to_string(),
+                their_did: "did:key:alice".to_string(),
+                their_services: Vec::new(),
+                their_keys: Vec::new(),
+                invitation_id: None,
+                state: ConnectionState::Completed,
+                created_time: 0,
+                updated_time: 0,
+            })
+            .handler("check-balance", |_: &Perform| {
+                BasicMessageBuilder::new()
+                    .message("Your balance is 42".to_string())
+                    .build()
+                    .map(Some)
+            })
+            .handler(
+                "request-statement",
+                |_: &Perform| -> Result<Option<Message>, &'static str> {
+                    Err("statement unavailable")
+                },
+            );
+
+        let menu_message = server
+            .handle(&ActionMenuBuilder::new().build_menu_request().unwrap())
+            .unwrap();
+        assert_eq!(menu_message.get_didcomm_header().m_type, MENU);
+
+        let perform = |name: &str, params: &[(&str, &str)]| {
+            ActionMenuBuilder::new()
+                .message(menu_message.clone())
+                .perform(
+                    name,
+                    params
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                )
+                .build_perform()
+                .unwrap()
+        };
+        let response = server.handle(&perform("check-balance", &[])).unwrap();
+        let header = response.get_didcomm_header();
+        assert_eq!(
+            header.m_type,
+            "https://didcomm.org/basicmessage/2.0/message"
+        );
+        assert_eq!(header.thid, menu_message.get_didcomm_header().thid);
+        assert_eq!(header.from.as_deref(), Some("did:key:bank"));
+        assert_eq!(header.to, vec!["did:key:alice".to_string()]);
+        let response = server
+            .handle(&perform("request-statement", &[("month", "2024-01")]))
+            .unwrap();
+        assert_eq!(
+            Menu::try_from(&response).unwrap().errormsg,
+            Some("statement unavailable".to_string())
+        );
+    }
+}
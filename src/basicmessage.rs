@@ -3,13 +3,117 @@
 //! The BasicMessage protocol describes a stateless, easy to support user message protocol. It has a single message type used to communicate.
 //! <https://didcomm.org/basicmessage/2.0/>
 
+use crate::connection::Connection;
+use crate::headers;
+use chrono::Utc;
 use didcomm_rs::Message;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// A received or sent basic message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BasicMessage {
+    pub id: String,
+    pub content: String,
+    pub lang: Option<String>,
+    /// seconds since Unix Epoch
+    pub created_time: u64,
+    pub sender: Option<String>,
+}
+
+impl BasicMessage {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.content.is_empty() {
+            return Err("empty content");
+        }
+        if let Some(lang) = self.lang.as_ref() {
+            validate_lang(lang)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_message(&self) -> Result<Message, &'static str> {
+        let mut message = Message::new()
+            .m_type("https://didcomm.org/basicmessage/2.0/message")
+            .body(&json!({ "content": self.content }).to_string());
+        if let Some(lang) = self.lang.as_ref() {
+            message = message.add_header_field("lang".to_string(), lang.to_string());
+        }
+        if let Some(sender) = self.sender.as_ref() {
+            message = message.from(sender);
+        }
+        let mut fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+        fields["id"] = json!(self.id);
+        let message = serde_json::from_value(fields).map_err(|_| "invalid message")?;
+        headers::timed(message, Some(self.created_time), None)
+    }
+}
+
+impl TryFrom<&Message> for BasicMessage {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if header.m_type != "https://didcomm.org/basicmessage/2.0/message" {
+            return Err("unsupported message");
+        }
+        let body: Value = serde_json::from_str(&message.get_body().map_err(|_| "invalid body")?)
+            .map_err(|_| "invalid body")?;
+        let fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+        let created_time = header.created_time.ok_or("missing created_time")?;
+
+        let basic_message = BasicMessage {
+            id: header.id.clone(),
+            content: body["content"]
+                .as_str()
+                .ok_or("missing content")?
+                .to_string(),
+            lang: fields["lang"].as_str().map(|lang| lang.to_string()),
+            created_time,
+            sender: header.from.clone(),
+        };
+        basic_message.validate()?;
+        Ok(basic_message)
+    }
+}
+
+impl TryFrom<Message> for BasicMessage {
+    type Error = &'static str;
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        BasicMessage::try_from(&message)
+    }
+}
+
+/// Checks the language is a tag like `en` or `de-CH`.
+fn validate_lang(lang: &str) -> Result<(), &'static str> {
+    let mut subtags = lang.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    if primary.len() < 2 || primary.len() > 8 || !primary.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err("invalid lang");
+    }
+    if subtags.any(|subtag| {
+        subtag.is_empty() || subtag.len() > 8 || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+    }) {
+        return Err("invalid lang");
+    }
+    Ok(())
+}
 
-#[derive(Default)]
 pub struct BasicMessageBuilder {
     message: Option<String>,
     lang: Option<String>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+    connection: Option<Connection>,
+}
+
+impl Default for BasicMessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BasicMessageBuilder {
@@ -17,6 +121,10 @@ impl BasicMessageBuilder {
         BasicMessageBuilder {
             message: None,
             lang: Some("en".to_string()),
+            created_time: None,
+            expires_time: None,
+            please_ack: false,
+            connection: None,
         }
     }
 
@@ -30,22 +138,103 @@ impl BasicMessageBuilder {
         self
     }
 
+    /// Time the message was created, defaults to now.
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
     pub fn build(&mut self) -> Result<Message, &'static str> {
-        let mut message = Message::new()
-            .m_type("https://didcomm.org/basicmessage/2.0/message")
-            .add_header_field(
-                "created_time".to_string(),
-                format!("{}", chrono::Utc::now().timestamp()),
-            )
-            .body(&json!({"content": self.message.as_ref().unwrap()}).to_string());
-        if self.lang.is_some() {
-            message = message
-                .add_header_field("lang".to_string(), self.lang.as_ref().unwrap().to_string());
+        let basic_message = BasicMessage {
+            id: Message::new().get_didcomm_header().id.clone(),
+            content: self.message.clone().ok_or("no message")?,
+            lang: self.lang.clone(),
+            created_time: self
+                .created_time
+                .unwrap_or_else(|| Utc::now().timestamp() as u64),
+            sender: None,
+        };
+        basic_message.validate()?;
+        let mut message = headers::timed(basic_message.to_message()?, None, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
         }
         Ok(message)
     }
 }
 
+/// Basic messages of each connection ordered by the time they were created.
+#[derive(Default)]
+pub struct ConversationHistory {
+    conversations: HashMap<String, Vec<BasicMessage>>,
+}
+
+impl ConversationHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the message to the conversation, a message with a known id is ignored.
+    pub fn add(&mut self, connection_id: &str, message: BasicMessage) -> &mut Self {
+        let conversation = self
+            .conversations
+            .entry(connection_id.to_string())
+            .or_default();
+        if !conversation.iter().any(|known| known.id == message.id) {
+            let index = conversation.partition_point(|known| {
+                (known.created_time, &known.id) <= (message.created_time, &message.id)
+            });
+            conversation.insert(index, message);
+        }
+        self
+    }
+
+    pub fn messages(&self, connection_id: &str) -> &[BasicMessage] {
+        self.conversations
+            .get(connection_id)
+            .map(|conversation| conversation.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Up to `limit` messages, oldest first, after skipping `offset` messages.
+    pub fn page(&self, connection_id: &str, offset: usize, limit: usize) -> &[BasicMessage] {
+        let messages = self.messages(connection_id);
+        let start = offset.min(messages.len());
+        let end = offset.saturating_add(limit).min(messages.len());
+        &messages[start..end]
+    }
+
+    /// Up to `limit` of the newest messages, oldest first.
+    pub fn latest(&self, connection_id: &str, limit: usize) -> &[BasicMessage] {
+        let messages = self.messages(connection_id);
+        &messages[messages.len().saturating_sub(limit)..]
+    }
+
+    pub fn len(&self, connection_id: &str) -> usize {
+        self.messages(connection_id).len()
+    }
+
+    pub fn is_empty(&self, connection_id: &str) -> bool {
+        self.messages(connection_id).is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,4 +284,73 @@ mod tests {
             "Hello World"
         );
     }
+
+    #[test]
+    fn test_build_without_message() {
+        assert_eq!(
+            BasicMessageBuilder::new().build().unwrap_err(),
+            "no message"
+        );
+        assert_eq!(
+            BasicMessageBuilder::new()
+                .message("Hello World".to_string())
+                .lang("not a language".to_string())
+                .build()
+                .unwrap_err(),
+            "invalid lang"
+        );
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = BasicMessageBuilder::new()
+            .message("Hello World".to_string())
+            .lang("de-CH".to_string())
+            .created_time(1_600_000_000)
+            .build()
+            .unwrap();
+        assert_eq!(
+            message.get_didcomm_header().created_time,
+            Some(1_600_000_000)
+        );
+
+        let basic_message = BasicMessage::try_from(&message).unwrap();
+        assert_eq!(basic_message.content, "Hello World");
+        assert_eq!(basic_message.lang.as_deref(), Some("de-CH"));
+        assert_eq!(basic_message.created_time, 1_600_000_000);
+        assert_eq!(basic_message.to_message(), Ok(message));
+    }
+
+    #[test]
+    fn test_conversation_history() {
+        let mut history = ConversationHistory::new();
+        for (i, content) in ["third", "first", "second"].iter().enumerate() {
+            let message = BasicMessageBuilder::new()
+                .message(content.to_string())
+                .created_time([30, 10, 20][i])
+                .build()
+                .unwrap();
+            let basic_message = BasicMessage::try_from(message).unwrap();
+            history
+                .add("connection", basic_message.clone())
+                .add("connection", basic_message);
+        }
+
+        assert_eq!(history.len("connection"), 3);
+        assert!(history.is_empty("other"));
+        let contents = |messages: &[BasicMessage]| -> Vec<String> {
+            messages.iter().map(|m| m.content.to_string()).collect()
+        };
+        assert_eq!(
+            contents(history.messages("connection")),
+            vec!["first", "second", "third"]
+        );
+        assert_eq!(contents(history.page("connection", 1, 1)), vec!["second"]);
+        assert_eq!(contents(history.page("connection", 2, 5)), vec!["third"]);
+        assert!(history.page("connection", 5, 5).is_empty());
+        assert_eq!(
+            contents(history.latest("connection", 2)),
+            vec!["second", "third"]
+        );
+    }
 }
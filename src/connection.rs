@@ -0,0 +1,205 @@
+//! # Connection
+//!
+//! A connection is the relationship two agents hold after a DID Exchange. It keeps our DID, the other party's DID
+//! together with the services and keys of their DID document, so other protocols can address messages to them.
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0023-did-exchange/README.md>
+
+use crate::service::Service;
+use didcomm_rs::Message;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    /// We sent a response to a request and wait for the complete message.
+    Responded,
+    /// The exchange completed and the connection can be used.
+    Completed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct Connection {
+    /// thread id of the DID Exchange that created the connection
+    pub id: String,
+    pub did: String,
+    pub their_did: String,
+    pub their_services: Vec<Service>,
+    pub their_keys: Vec<String>,
+    pub invitation_id: Option<String>,
+    pub state: ConnectionState,
+    pub created_time: i64,
+    pub updated_time: i64,
+}
+
+impl Connection {
+    /// Creates a connection from the request or response the other party sent during a DID Exchange.
+    pub fn from_exchange(did: String, message: &Message) -> Result<Self, &'static str> {
+        let header = message.get_didcomm_header();
+        let (id, invitation_id, state) = match header.m_type.as_str() {
+            "https://didcomm.org/didexchange/1.0/request" => (
+                header.id.clone(),
+                header.pthid.clone(),
+                ConnectionState::Responded,
+            ),
+            "https://didcomm.org/didexchange/1.0/response" => (
+                header.thid.clone().ok_or("missing thid")?,
+                None,
+                ConnectionState::Completed,
+            ),
+            _ => return Err("unsupported message"),
+        };
+
+        let fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+        let their_did = fields["did"].as_str().ok_or("missing did")?.to_string();
+        let did_doc: Value = match fields["did_doc~attach"].as_str() {
+            Some(did_doc) => serde_json::from_str(did_doc).map_err(|_| "invalid did_doc")?,
+            None => Value::Null,
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        Ok(Connection {
+            id,
+            did,
+            their_did,
+            their_services: Self::services_from_did_doc(&did_doc),
+            their_keys: Self::keys_from_did_doc(&did_doc),
+            invitation_id,
+            state,
+            created_time: now,
+            updated_time: now,
+        })
+    }
+
+    /// Marks the connection as completed once the complete message of the exchange arrived.
+    pub fn complete(&mut self, message: &Message) -> Result<&mut Self, &'static str> {
+        let header = message.get_didcomm_header();
+        if header.m_type != "https://didcomm.org/didexchange/1.0/complete" {
+            return Err("unsupported message");
+        }
+        if header.thid.as_ref() != Some(&self.id) {
+            return Err("thid does not match connection");
+        }
+        self.state = ConnectionState::Completed;
+        self.updated_time = chrono::Utc::now().timestamp();
+        Ok(self)
+    }
+
+    pub fn invitation(&mut self, invitation: &Message) -> &mut Self {
+        self.invitation_id = Some(invitation.get_didcomm_header().id.clone());
+        if self.their_services.is_empty() {
+            let fields = serde_json::to_value(invitation).unwrap_or_default();
+            if let Some(services) = fields["services"].as_str() {
+                self.their_services = serde_json::from_str(services).unwrap_or_default();
+            }
+        }
+        self
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state == ConnectionState::Completed
+    }
+
+    /// Sets our DID as sender and their DID as recipient of the message.
+    pub fn address(&self, message: Message) -> Message {
+        message.from(&self.did).to(&[&self.their_did])
+    }
+
+    fn keys_from_did_doc(did_doc: &Value) -> Vec<String> {
+        did_doc["verificationMethod"]
+            .as_array()
+            .map(|methods| {
+                methods
+                    .iter()
+                    .filter_map(|method| method["publicKeyBase58"].as_str())
+                    .map(|key| key.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn services_from_did_doc(did_doc: &Value) -> Vec<Service> {
+        did_doc["service"]
+            .as_array()
+            .map(|services| {
+                services
+                    .iter()
+                    .filter_map(|service| serde_json::from_value(service.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{invitation::GoalCode, DidExchangeResponseBuilder, InvitationBuilder};
+    use did_key::{generate, DIDCore, X25519KeyPair, CONFIG_LD_PUBLIC};
+
+    #[test]
+    fn test_connection_from_exchange() {
+        let alice_key = generate::<X25519KeyPair>(None);
+        let bob_key = generate::<X25519KeyPair>(None);
+        let alice_did = alice_key.get_did_document(CONFIG_LD_PUBLIC).id;
+        let bob_did = bob_key.get_did_document(CONFIG_LD_PUBLIC).id;
+
+        let service = Service::new(
+            bob_did.to_string(),
+            "https://example.com".to_string(),
+            vec![],
+        )
+        .unwrap();
+        let invitation = InvitationBuilder::new()
+            .goal_code(GoalCode::Other("aries.rel.build".to_string()))
+            .services(vec![service.clone()])
+            .build()
+            .unwrap();
+
+        let request = DidExchangeResponseBuilder::new()
+            .message(invitation.clone())
+            .did(alice_did.to_string())
+            .did_doc(serde_json::to_value(alice_key.get_did_document(CONFIG_LD_PUBLIC)).unwrap())
+            .build()
+            .unwrap();
+
+        let mut bob_connection = Connection::from_exchange(bob_did.to_string(), &request).unwrap();
+        assert_eq!(bob_connection.their_did, alice_did);
+        assert_eq!(bob_connection.state, ConnectionState::Responded);
+        assert_eq!(
+            bob_connection.invitation_id.as_ref().unwrap(),
+            &invitation.get_didcomm_header().id
+        );
+
+        let response = DidExchangeResponseBuilder::new()
+            .message(request)
+            .did(bob_did.to_string())
+            .did_doc(serde_json::to_value(bob_key.get_did_document(CONFIG_LD_PUBLIC)).unwrap())
+            .build()
+            .unwrap();
+
+        let mut alice_connection =
+            Connection::from_exchange(alice_did.to_string(), &response).unwrap();
+        alice_connection.invitation(&invitation);
+        assert!(alice_connection.is_completed());
+        assert_eq!(alice_connection.their_did, bob_did);
+        assert_eq!(alice_connection.their_keys.len(), 1);
+        assert_eq!(alice_connection.their_services, vec![service]);
+
+        let complete = DidExchangeResponseBuilder::new()
+            .message(response)
+            .build()
+            .unwrap();
+        bob_connection.complete(&complete).unwrap();
+        assert!(bob_connection.is_completed());
+        assert_eq!(bob_connection.id, alice_connection.id);
+
+        let message = alice_connection.address(Message::new());
+        assert_eq!(message.get_didcomm_header().to, vec![bob_did]);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&alice_connection).unwrap()
+        );
+    }
+}
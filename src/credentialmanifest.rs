@@ -0,0 +1,345 @@
+//! # Credential Manifest
+//!
+//! An issuer describes in a manifest which credentials it issues and what a holder has to present to get them. The
+//! holder applies with a credential application, the issuer answers with a credential response that maps the output
+//! descriptors to the issued credentials. Applications and responses are embedded in presentations like presentation
+//! submissions.
+//! <https://identity.foundation/credential-manifest/>
+
+use crate::issuecredential::formats::{
+    CREDENTIAL_APPLICATION, CREDENTIAL_MANIFEST, CREDENTIAL_RESPONSE,
+};
+use crate::issuecredential::formatted_attachments;
+use crate::presentationexchange::{
+    DescriptorMapEntry, PresentationDefinition, PresentationSubmission,
+};
+use crate::verifiablecredential::VerifiablePresentation;
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const CONTEXT_APPLICATION: &str =
+    "https://identity.foundation/credential-manifest/application/v1";
+pub const CONTEXT_RESPONSE: &str = "https://identity.foundation/credential-manifest/response/v1";
+/// Context of `credential_fulfillment`, the name of credential responses before version 1.0 of the specification.
+pub const CONTEXT_FULFILLMENT: &str =
+    "https://identity.foundation/credential-manifest/fulfillment/v1";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialManifest {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec_version: Option<String>,
+    pub issuer: ManifestIssuer,
+    /// credentials the issuer issues
+    pub output_descriptors: Vec<OutputDescriptor>,
+    /// claim formats the issuer issues and accepts in applications
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+    /// what the holder has to present in the application
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presentation_definition: Option<PresentationDefinition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestIssuer {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub styles: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OutputDescriptor {
+    pub id: String,
+    /// URI of the schema of the credential
+    pub schema: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// how wallets display the credential
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub styles: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialApplication {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec_version: Option<String>,
+    pub manifest_id: String,
+    /// claim format of the application
+    pub format: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialResponse {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec_version: Option<String>,
+    pub manifest_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fulfillment: Option<Fulfillment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denial: Option<Denial>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fulfillment {
+    /// output descriptors mapped to the credentials of the presentation
+    pub descriptor_map: Vec<DescriptorMapEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Denial {
+    pub reason: String,
+    /// input descriptors the application did not satisfy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_descriptors: Vec<String>,
+}
+
+impl CredentialManifest {
+    /// Reads the manifest attached to an offer of issue credential 2.1 or 3.0, so the holder sees what it receives
+    /// and has to present before requesting. The manifest may be wrapped with WACI DIDComm options.
+    pub fn from_offer(offer: &Message) -> Result<Self, &'static str> {
+        let (_, data) = formatted_attachments(offer)
+            .into_iter()
+            .find(|(format, _)| format.as_deref() == Some(CREDENTIAL_MANIFEST))
+            .ok_or("no credential manifest")?;
+        let mut manifest: Value =
+            serde_json::from_slice(&data).map_err(|_| "invalid credential manifest")?;
+        if let Some(wrapped) = manifest.get("credential_manifest") {
+            manifest = wrapped.clone();
+        }
+        serde_json::from_value(manifest).map_err(|_| "invalid credential manifest")
+    }
+
+    /// Creates an application and picks the credentials for the presentation definition of the manifest. The
+    /// submission is returned if the manifest has a definition, both are embedded in the presentation of the
+    /// picked credentials.
+    pub fn apply(
+        &self,
+        credentials: &[Value],
+        format: &str,
+    ) -> Result<
+        (
+            CredentialApplication,
+            Option<PresentationSubmission>,
+            Vec<Value>,
+        ),
+        &'static str,
+    > {
+        let mut claim_format = json!({});
+        claim_format[format] = json!({});
+        let application = CredentialApplication {
+            id: Uuid::new_v4().to_string(),
+            spec_version: self.spec_version.clone(),
+            manifest_id: self.id.to_string(),
+            format: claim_format,
+        };
+        match self.presentation_definition.as_ref() {
+            Some(definition) => {
+                let (submission, selected) = definition.submit(credentials, format)?;
+                Ok((application, Some(submission), selected))
+            }
+            None => Ok((application, None, Vec::new())),
+        }
+    }
+
+    /// Checks the application attached to a request of issue credential 2.1 or 3.0, returns it with its presentation.
+    pub fn check_request(
+        &self,
+        request: &Message,
+    ) -> Result<(CredentialApplication, Value), &'static str> {
+        let presentation = attached(request, CREDENTIAL_APPLICATION, "no credential application")?;
+        Ok((self.check_application(&presentation)?, presentation))
+    }
+
+    /// Checks the application embedded in the presentation is for the manifest and presents what it asks for.
+    pub fn check_application(
+        &self,
+        presentation: &Value,
+    ) -> Result<CredentialApplication, &'static str> {
+        let application = CredentialApplication::from_presentation(presentation)?;
+        if application.manifest_id != self.id {
+            return Err("invalid manifest");
+        }
+        if let Some(definition) = self.presentation_definition.as_ref() {
+            PresentationSubmission::from_presentation(presentation)?
+                .verify(definition, presentation)?;
+        }
+        Ok(application)
+    }
+
+    /// Responds with the credentials of the output descriptors, in the order of the descriptors.
+    pub fn respond(&self, application_id: Option<&str>, format: &str) -> CredentialResponse {
+        CredentialResponse {
+            id: Uuid::new_v4().to_string(),
+            spec_version: self.spec_version.clone(),
+            manifest_id: self.id.to_string(),
+            application_id: application_id.map(String::from),
+            fulfillment: Some(Fulfillment {
+                descriptor_map: self
+                    .output_descriptors
+                    .iter()
+                    .enumerate()
+                    .map(|(index, descriptor)| DescriptorMapEntry {
+                        id: descriptor.id.to_string(),
+                        format: format.to_string(),
+                        path: format!("$.verifiableCredential[{}]", index),
+                        path_nested: None,
+                    })
+                    .collect(),
+            }),
+            denial: None,
+        }
+    }
+}
+
+impl CredentialApplication {
+    /// Adds the application to the presentation as `credential_application`, with its context.
+    pub fn embed(&self, presentation: &mut VerifiablePresentation) {
+        let context = json!(CONTEXT_APPLICATION);
+        if !presentation.context.contains(&context) {
+            presentation.context.push(context);
+        }
+        presentation
+            .properties
+            .insert("credential_application".to_string(), json!(self));
+    }
+
+    pub fn from_presentation(presentation: &Value) -> Result<Self, &'static str> {
+        serde_json::from_value(presentation["credential_application"].clone())
+            .map_err(|_| "no credential application")
+    }
+}
+
+impl CredentialResponse {
+    /// Adds the response to the presentation of the issued credentials as `credential_response`, with its context.
+    pub fn embed(&self, presentation: &mut VerifiablePresentation) {
+        let context = json!(CONTEXT_RESPONSE);
+        if !presentation.context.contains(&context) {
+            presentation.context.push(context);
+        }
+        presentation
+            .properties
+            .insert("credential_response".to_string(), json!(self));
+    }
+
+    pub fn from_presentation(presentation: &Value) -> Result<Self, &'static str> {
+        serde_json::from_value(presentation["credential_response"].clone())
+            .map_err(|_| "no credential response")
+    }
+
+    /// Reads the response attached to an issue-credential, returns it with its presentation.
+    pub fn from_issue(issue: &Message) -> Result<(Self, Value), &'static str> {
+        let presentation = attached(issue, CREDENTIAL_RESPONSE, "no credential response")?;
+        Ok((Self::from_presentation(&presentation)?, presentation))
+    }
+}
+
+/// The JSON of the first attachment in the format.
+fn attached(message: &Message, format: &str, missing: &'static str) -> Result<Value, &'static str> {
+    let (_, data) = formatted_attachments(message)
+        .into_iter()
+        .find(|(attachment_format, _)| attachment_format.as_deref() == Some(format))
+        .ok_or(missing)?;
+    serde_json::from_slice(&data).map_err(|_| "invalid attachment")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifiablecredential::{DataModelVersion, VerifiableCredential};
+    use crate::IssueCredentialResponseBuilder;
+
+    #[test]
+    fn test_manifest_offer() {
+        let manifest: CredentialManifest = serde_json::from_value(json!({
+            "id": "WA-DL-CLASS-A",
+            "spec_version": "https://identity.foundation/credential-manifest/spec/v1.0.0/",
+            "issuer": { "id": "did:example:123", "name": "Washington State Government" },
+            "output_descriptors": [{
+                "id": "driver_license_output",
+                "schema": "https://schema.org/EducationalOccupationalCredential",
+                "display": { "title": { "text": "Washington State Driver License" } }
+            }],
+            "presentation_definition": {
+                "id": "32f54163-7166-48f1-93d8-ff217bdb0653",
+                "input_descriptors": [{
+                    "id": "passport",
+                    "constraints": { "fields": [{ "path": ["$.credentialSubject.birthDate"] }] }
+                }]
+            }
+        }))
+        .unwrap();
+        let offer = IssueCredentialResponseBuilder::new()
+            .credential_manifest(manifest.clone())
+            .build_offer_credential()
+            .unwrap();
+
+        let offered = CredentialManifest::from_offer(&offer).unwrap();
+        assert_eq!(offered, manifest);
+        assert_eq!(offered.output_descriptors[0].id, "driver_license_output");
+
+        let passport = json!(VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            "did:example:passport-office",
+            json!({ "id": "did:example:alice", "birthDate": "1958-07-17" })
+        ));
+        let (application, submission, selected) = offered.apply(&[passport], "ldp_vc").unwrap();
+        let mut presentation = VerifiablePresentation::new(
+            DataModelVersion::V2_0,
+            Some("did:example:alice"),
+            selected
+                .into_iter()
+                .map(|credential| serde_json::from_value(credential).unwrap())
+                .collect(),
+        );
+        application.embed(&mut presentation);
+        submission.unwrap().embed(&mut presentation);
+        let request = IssueCredentialResponseBuilder::new()
+            .credential_application(presentation.clone())
+            .build_request_credential()
+            .unwrap();
+        assert_eq!(
+            manifest.check_request(&request),
+            Ok((application.clone(), json!(presentation)))
+        );
+        presentation.verifiable_credential.clear();
+        let unsatisfied = IssueCredentialResponseBuilder::new()
+            .credential_application(presentation)
+            .build_request_credential()
+            .unwrap();
+        assert!(manifest.check_request(&unsatisfied).is_err());
+        assert_eq!(
+            manifest.check_request(&offer),
+            Err("no credential application")
+        );
+        assert_eq!(
+            manifest.apply(&[], "ldp_vc").unwrap_err(),
+            "no matching credential"
+        );
+
+        let response = manifest.respond(Some(&application.id), "ldp_vc");
+        let mut issued = VerifiablePresentation::new(DataModelVersion::V2_0, None, vec![]);
+        response.embed(&mut issued);
+        let issue = IssueCredentialResponseBuilder::new()
+            .credential_response(issued)
+            .build_issue_credential()
+            .unwrap();
+        let (response, _) = CredentialResponse::from_issue(&issue).unwrap();
+        assert_eq!(response.application_id, Some(application.id));
+        assert_eq!(
+            response.fulfillment.unwrap().descriptor_map[0].id,
+            "driver_license_output"
+        );
+    }
+}
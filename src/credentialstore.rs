@@ -0,0 +1,566 @@
+//! # Credential Store
+//!
+//! The wallet of a holder. Credentials received with issue credential are kept with the exchange they came from and
+//! can be queried by type, issuer, schema and attributes, or by the input descriptors of a presentation definition to
+//! answer a request for a presentation. Credentials the [CredentialRecords] of the holder find revoked are marked as
+//! revoked in the store.
+
+use crate::connection::Connection;
+use crate::dataintegrity::DataIntegrityVerifier;
+use crate::issuecredential::formats::{JWT_VC, LD_PROOF_VC, MANIFEST_FORMATS, SD_JWT_VC};
+use crate::issuecredential::formatted_attachments;
+use crate::jwt::{self, SdJwt};
+use crate::presentationexchange::PresentationDefinition;
+use crate::revocationnotification::CredentialRecords;
+use crate::verifiablecredential::VerifiableCredential;
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// A received credential with the exchange it was issued in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredCredential {
+    pub id: String,
+    pub credential: VerifiableCredential,
+    /// the credential as received if it is not in JSON, like a JWT
+    pub encoded: Option<String>,
+    pub format: Option<String>,
+    /// id of the connection the credential was received over
+    pub connection_id: Option<String>,
+    /// thread of the issue-credential protocol
+    pub thid: String,
+    pub issuance_date: Option<String>,
+    pub received_time: i64,
+    #[serde(default)]
+    pub revoked: bool,
+    pub revocation_comment: Option<String>,
+}
+
+impl StoredCredential {
+    /// Reads and verifies the credentials of an issue-credential message of version 2.1 or 3.0. JWT and SD-JWT
+    /// credentials are verified with the key of their issuer, the proof of JSON-LD credentials with the verifier.
+    /// Their status is not checked, status lists are only known when the credential is presented. Credential Manifest
+    /// attachments are skipped, credentials in other formats fail.
+    pub fn from_issued(
+        message: &Message,
+        connection: Option<&Connection>,
+        verifier: &DataIntegrityVerifier,
+    ) -> Result<Vec<Self>, &'static str> {
+        let header = message.get_didcomm_header();
+        let thid = header.thid.clone().unwrap_or_else(|| header.id.clone());
+        let now = chrono::Utc::now().timestamp();
+        let mut credentials = Vec::new();
+        for (format, data) in formatted_attachments(message) {
+            let (credential, encoded) = match format.as_deref() {
+                Some(format) if MANIFEST_FORMATS.contains(&format) => continue,
+                Some(JWT_VC) => {
+                    let encoded = String::from_utf8(data).map_err(|_| "invalid credential")?;
+                    (jwt::verify_credential(&encoded)?, Some(encoded))
+                }
+                Some(SD_JWT_VC) => {
+                    let encoded = String::from_utf8(data).map_err(|_| "invalid credential")?;
+                    let sd_jwt: SdJwt = encoded.parse()?;
+                    (sd_jwt.verify_credential()?, Some(encoded))
+                }
+                None | Some(LD_PROOF_VC) => {
+                    let credential: VerifiableCredential =
+                        serde_json::from_slice(&data).map_err(|_| "invalid credential")?;
+                    verifier.verify_proof(&credential)?;
+                    (credential, None)
+                }
+                Some(_) => return Err("unsupported credential format"),
+            };
+            credentials.push(StoredCredential {
+                id: Uuid::new_v4().to_string(),
+                issuance_date: credential
+                    .issuance_date
+                    .clone()
+                    .or_else(|| credential.valid_from.clone()),
+                credential,
+                encoded,
+                format,
+                connection_id: connection.map(|connection| connection.id.to_string()),
+                thid: thid.to_string(),
+                received_time: now,
+                revoked: false,
+                revocation_comment: None,
+            });
+        }
+        if credentials.is_empty() {
+            return Err("no credential");
+        }
+        Ok(credentials)
+    }
+}
+
+/// Criteria a credential has to meet all of.
+#[derive(Default, Debug, Clone)]
+pub struct CredentialQuery {
+    types: Vec<String>,
+    issuer: Option<String>,
+    schema: Option<String>,
+    attributes: Vec<(String, Value)>,
+}
+
+impl CredentialQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn type_(&mut self, type_: &str) -> &mut Self {
+        self.types.push(type_.to_string());
+        self
+    }
+
+    pub fn issuer(&mut self, issuer: &str) -> &mut Self {
+        self.issuer = Some(issuer.to_string());
+        self
+    }
+
+    /// Id of the `credentialSchema` of the credential.
+    pub fn schema(&mut self, schema: &str) -> &mut Self {
+        self.schema = Some(schema.to_string());
+        self
+    }
+
+    /// Value of a claim of the credential subject.
+    pub fn attribute(&mut self, name: &str, value: Value) -> &mut Self {
+        self.attributes.push((name.to_string(), value));
+        self
+    }
+
+    pub fn matches(&self, stored: &StoredCredential) -> bool {
+        let credential = &stored.credential;
+        let one_or_many = |value: &Value| match value {
+            Value::Array(values) => values.clone(),
+            Value::Null => Vec::new(),
+            value => vec![value.clone()],
+        };
+        let schemas = credential
+            .credential_schema
+            .as_ref()
+            .map(one_or_many)
+            .unwrap_or_default();
+        let subjects = one_or_many(&credential.credential_subject);
+        self.types
+            .iter()
+            .all(|type_| credential.type_.contains(type_))
+            && self
+                .issuer
+                .as_ref()
+                .is_none_or(|issuer| credential.issuer.id() == issuer)
+            && self.schema.as_ref().is_none_or(|schema| {
+                schemas
+                    .iter()
+                    .any(|candidate| candidate["id"] == json!(schema))
+            })
+            && self.attributes.iter().all(|(name, value)| {
+                subjects
+                    .iter()
+                    .any(|subject| &subject[name.as_str()] == value)
+            })
+    }
+}
+
+pub trait CredentialStore {
+    fn add(&mut self, credential: StoredCredential) -> Result<(), &'static str>;
+    fn get(&self, id: &str) -> Option<StoredCredential>;
+    fn remove(&mut self, id: &str) -> Result<Option<StoredCredential>, &'static str>;
+    /// Replaces the stored credential with the same id.
+    fn update(&mut self, credential: StoredCredential) -> Result<(), &'static str>;
+    /// All credentials in the order they were added.
+    fn all(&self) -> Vec<StoredCredential>;
+
+    /// Verifies and stores the credentials of an issue-credential message and returns their ids.
+    fn add_issued(
+        &mut self,
+        message: &Message,
+        connection: Option<&Connection>,
+        verifier: &DataIntegrityVerifier,
+    ) -> Result<Vec<String>, &'static str> {
+        let mut ids = Vec::new();
+        for credential in StoredCredential::from_issued(message, connection, verifier)? {
+            ids.push(credential.id.to_string());
+            self.add(credential)?;
+        }
+        Ok(ids)
+    }
+
+    /// Passes the revocation notification to the records of the holder and marks the stored credentials they
+    /// revoked, returns the stored credentials. Credentials are matched by their issue thread and content.
+    fn revoke(
+        &mut self,
+        records: &mut CredentialRecords,
+        message: &Message,
+    ) -> Result<Vec<StoredCredential>, &'static str> {
+        let revoked: Vec<_> = records.revoke(message)?.into_iter().cloned().collect();
+        let mut stored_revoked = Vec::new();
+        for mut stored in self.all() {
+            let record = revoked.iter().find(|record| {
+                record.thid == stored.thid && record.credential == stored.credential
+            });
+            if let Some(record) = record {
+                stored.revoked = true;
+                stored.revocation_comment = record.revocation_comment.clone();
+                self.update(stored.clone())?;
+                stored_revoked.push(stored);
+            }
+        }
+        Ok(stored_revoked)
+    }
+
+    fn find(&self, query: &CredentialQuery) -> Vec<StoredCredential> {
+        self.all()
+            .into_iter()
+            .filter(|credential| query.matches(credential))
+            .collect()
+    }
+
+    /// The credentials matching each input descriptor of the definition, in the order of the descriptors.
+    fn find_for(
+        &self,
+        definition: &PresentationDefinition,
+    ) -> Result<Vec<Vec<StoredCredential>>, &'static str> {
+        let credentials = self.all();
+        let documents: Vec<Value> = credentials
+            .iter()
+            .map(|stored| json!(stored.credential))
+            .collect();
+        Ok(definition
+            .evaluate(&documents)?
+            .into_iter()
+            .map(|matches| {
+                matches
+                    .into_iter()
+                    .map(|index| credentials[index].clone())
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryCredentialStore {
+    credentials: Vec<StoredCredential>,
+}
+
+impl MemoryCredentialStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CredentialStore for MemoryCredentialStore {
+    fn add(&mut self, credential: StoredCredential) -> Result<(), &'static str> {
+        if self.get(&credential.id).is_some() {
+            return Err("credential exists");
+        }
+        self.credentials.push(credential);
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Option<StoredCredential> {
+        self.credentials
+            .iter()
+            .find(|credential| credential.id == id)
+            .cloned()
+    }
+
+    fn remove(&mut self, id: &str) -> Result<Option<StoredCredential>, &'static str> {
+        let position = self
+            .credentials
+            .iter()
+            .position(|credential| credential.id == id);
+        Ok(position.map(|position| self.credentials.remove(position)))
+    }
+
+    fn update(&mut self, credential: StoredCredential) -> Result<(), &'static str> {
+        let stored = self
+            .credentials
+            .iter_mut()
+            .find(|stored| stored.id == credential.id)
+            .ok_or("unknown credential")?;
+        *stored = credential;
+        Ok(())
+    }
+
+    fn all(&self) -> Vec<StoredCredential> {
+        self.credentials.clone()
+    }
+}
+
+/// Keeps the credentials in a JSON file, written on every change.
+pub struct FileCredentialStore {
+    path: PathBuf,
+    memory: MemoryCredentialStore,
+}
+
+impl FileCredentialStore {
+    /// Opens the store at the path, the file is created with the first credential.
+    pub fn open(path: &Path) -> Result<Self, &'static str> {
+        let credentials = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|_| "invalid store")?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(_) => return Err("can not read store"),
+        };
+        Ok(FileCredentialStore {
+            path: path.to_path_buf(),
+            memory: MemoryCredentialStore { credentials },
+        })
+    }
+
+    fn save(&self) -> Result<(), &'static str> {
+        let content =
+            serde_json::to_string(&self.memory.credentials).map_err(|_| "invalid store")?;
+        // replaces the store at once so a failed write does not lose the credentials
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, content).map_err(|_| "can not write store")?;
+        fs::rename(&temporary, &self.path).map_err(|_| "can not write store")
+    }
+}
+
+impl CredentialStore for FileCredentialStore {
+    fn add(&mut self, credential: StoredCredential) -> Result<(), &'static str> {
+        self.memory.add(credential)?;
+        self.save()
+    }
+
+    fn get(&self, id: &str) -> Option<StoredCredential> {
+        self.memory.get(id)
+    }
+
+    fn remove(&mut self, id: &str) -> Result<Option<StoredCredential>, &'static str> {
+        let removed = self.memory.remove(id)?;
+        if removed.is_some() {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn update(&mut self, credential: StoredCredential) -> Result<(), &'static str> {
+        self.memory.update(credential)?;
+        self.save()
+    }
+
+    fn all(&self) -> Vec<StoredCredential> {
+        self.memory.all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataintegrity::DataIntegrityIssuer;
+    use crate::issuecredentialv3::IssueCredentialV3ResponseBuilder;
+    use crate::jwt::JwtIssuer;
+    use crate::revocationnotification::RevocationNotificationBuilder;
+    use crate::statuslist::StatusListManager;
+    use crate::verifiablecredential::DataModelVersion;
+    use crate::IssueCredentialResponseBuilder;
+    use did_key::{generate, Ed25519KeyPair};
+    use serde_json::Map;
+
+    fn credential(
+        issuer: &DataIntegrityIssuer,
+        type_: &str,
+        subject: Value,
+    ) -> VerifiableCredential {
+        let mut credential =
+            VerifiableCredential::new(DataModelVersion::V2_0, &issuer.did(), subject);
+        credential.type_.push(type_.to_string());
+        credential.credential_schema = Some(
+            json!({ "id": format!("https://example.com/schemas/{}", type_), "type": "JsonSchema" }),
+        );
+        issuer.sign(&credential).unwrap()
+    }
+
+    #[test]
+    fn test_query_credentials() {
+        let issuer = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer")));
+        let passport_office =
+            DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"passport-office"))).unwrap();
+        let verifier = DataIntegrityVerifier::new();
+        let mut store = MemoryCredentialStore::new();
+        let passport = credential(
+            &passport_office,
+            "Passport",
+            json!({ "birthDate": "1958-07-17" }),
+        );
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(passport.clone())
+            .build_issue_credential()
+            .unwrap();
+        store.add_issued(&issued, None, &verifier).unwrap();
+        let jwt = issuer
+            .sign_credential(&VerifiableCredential::new(
+                DataModelVersion::V1_1,
+                &issuer.did(),
+                json!({ "id": "did:example:alice", "degree": "BSc" }),
+            ))
+            .unwrap();
+        let issued = IssueCredentialV3ResponseBuilder::new()
+            .attachment(JWT_VC, json!(jwt))
+            .build_issue_credential()
+            .unwrap();
+        let ids = store.add_issued(&issued, None, &verifier).unwrap();
+        let stored = store.get(&ids[0]).unwrap();
+        assert_eq!(stored.encoded, Some(jwt));
+        assert_eq!(stored.thid, issued.get_didcomm_header().id);
+
+        let mut forged = passport;
+        forged.credential_subject["birthDate"] = json!("2000-01-01");
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(forged)
+            .build_issue_credential()
+            .unwrap();
+        assert_eq!(
+            store.add_issued(&issued, None, &verifier),
+            Err("invalid signature")
+        );
+        let holder = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder")));
+        let mut claims = Map::new();
+        claims.insert("sub".to_string(), json!(holder.did()));
+        claims.insert("membership".to_string(), json!("gold"));
+        let sd_jwt = SdJwt::issue(
+            &issuer,
+            "https://example.com/Membership",
+            claims,
+            &["membership"],
+            &holder.jwk().unwrap(),
+        )
+        .unwrap()
+        .to_string();
+        let issued = IssueCredentialV3ResponseBuilder::new()
+            .attachment(SD_JWT_VC, json!(sd_jwt))
+            .build_issue_credential()
+            .unwrap();
+        let ids = store.add_issued(&issued, None, &verifier).unwrap();
+        let membership = store.get(&ids[0]).unwrap();
+        assert_eq!(membership.encoded, Some(sd_jwt));
+        assert_eq!(
+            membership.credential.credential_subject,
+            json!({ "id": holder.did(), "membership": "gold" })
+        );
+
+        let found = store.find(
+            CredentialQuery::new()
+                .type_("Passport")
+                .issuer(&passport_office.did())
+                .schema("https://example.com/schemas/Passport")
+                .attribute("birthDate", json!("1958-07-17")),
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].credential.credential_subject["birthDate"],
+            "1958-07-17"
+        );
+        assert!(store
+            .find(CredentialQuery::new().attribute("birthDate", json!("2000-01-01")))
+            .is_empty());
+        assert_eq!(
+            store
+                .find(CredentialQuery::new().issuer(&issuer.did()))
+                .len(),
+            2
+        );
+
+        let definition: PresentationDefinition = serde_json::from_value(json!({
+            "id": "degree",
+            "input_descriptors": [{
+                "id": "degree",
+                "constraints": { "fields": [{ "path": ["$.credentialSubject.degree"] }] }
+            }]
+        }))
+        .unwrap();
+        assert_eq!(store.find_for(&definition), Ok(vec![vec![stored]]));
+    }
+
+    #[test]
+    fn test_revoke_credentials() {
+        let issuer = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap();
+        let mut manager = StatusListManager::new("https://example.com/status/1", "revocation");
+        let mut passport = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            &issuer.did(),
+            json!({ "id": "did:example:alice" }),
+        );
+        let mut license = passport.clone();
+        manager.allocate(&mut passport).unwrap();
+        manager.allocate(&mut license).unwrap();
+        // the status list is not known to the holder
+        let verifier = DataIntegrityVerifier::new();
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(issuer.sign(&passport).unwrap())
+            .build_issue_credential()
+            .unwrap()
+            .from(&issuer.did());
+        let thid = issued.get_didcomm_header().id.to_string();
+        let reissued = IssueCredentialResponseBuilder::new()
+            .credential(issuer.sign(&license).unwrap())
+            .build_issue_credential()
+            .unwrap()
+            .from(&issuer.did())
+            .thid(&thid);
+        let path = std::env::temp_dir().join(format!("credentials-{}.json", Uuid::new_v4()));
+        let mut store = FileCredentialStore::open(&path).unwrap();
+        let mut records = CredentialRecords::new();
+        let mut ids = Vec::new();
+        for message in [&issued, &reissued] {
+            records.add_issued(message).unwrap();
+            ids.extend(store.add_issued(message, None, &verifier).unwrap());
+        }
+
+        let notification = RevocationNotificationBuilder::new()
+            .credential(&passport)
+            .comment("Lost".to_string())
+            .issue_thid(thid)
+            .build()
+            .unwrap();
+        assert_eq!(
+            store.revoke(
+                &mut records,
+                &notification.clone().from("did:example:mallory")
+            ),
+            Err("unknown credential")
+        );
+        let revoked = store
+            .revoke(&mut records, &notification.from(&issuer.did()))
+            .unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].id, ids[0]);
+
+        let reopened = FileCredentialStore::open(&path).unwrap();
+        let passport = reopened.get(&ids[0]).unwrap();
+        assert!(passport.revoked);
+        assert_eq!(passport.revocation_comment, Some("Lost".to_string()));
+        assert!(!reopened.get(&ids[1]).unwrap().revoked);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store() {
+        let issuer = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap();
+        let path = std::env::temp_dir().join(format!("credentials-{}.json", Uuid::new_v4()));
+        let mut store = FileCredentialStore::open(&path).unwrap();
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(credential(
+                &issuer,
+                "Passport",
+                json!({ "birthDate": "1958-07-17" }),
+            ))
+            .build_issue_credential()
+            .unwrap();
+        let ids = store
+            .add_issued(&issued, None, &DataIntegrityVerifier::new())
+            .unwrap();
+
+        let mut reopened = FileCredentialStore::open(&path).unwrap();
+        assert_eq!(reopened.all(), store.all());
+        assert_eq!(reopened.remove(&ids[0]).unwrap().unwrap().id, ids[0]);
+        assert!(FileCredentialStore::open(&path).unwrap().all().is_empty());
+        fs::remove_file(path).unwrap();
+    }
+}
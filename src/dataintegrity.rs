@@ -0,0 +1,403 @@
+//! # Data Integrity
+//!
+//! Signs and verifies JSON-LD credentials and presentations with Data Integrity proofs of did:key Ed25519 keys.
+//! <https://www.w3.org/TR/vc-data-integrity/>
+//! <https://www.w3.org/TR/vc-di-eddsa/>
+//!
+//! The proofs use the `eddsa-rdfc-2022` cryptosuite, which canonicalizes the document as RDF dataset with RDFC-1.0.
+//! Signing and verification are offline, contexts come from the bundled documents and those added to the issuer or
+//! verifier, documents with unknown contexts or terms the contexts do not define are rejected.
+
+use crate::error::ProtocolError;
+use crate::issuecredential::{formats, IssueCredentialResponseBuilder};
+use crate::jsonld::{canonicalize, DocumentLoader};
+use crate::jwt::check_ed25519;
+use crate::statuslist::check_status;
+use crate::verifiablecredential::{
+    DataModelVersion, PresentedCredential, VerifiableCredential, VerifiablePresentation,
+};
+use base58::{FromBase58, ToBase58};
+use chrono::{SecondsFormat, Utc};
+use did_key::{CoreSign, Fingerprint, PatchedKeyPair};
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+pub const PROOF_TYPE: &str = "DataIntegrityProof";
+pub const CRYPTOSUITE: &str = "eddsa-rdfc-2022";
+pub const CONTEXT_DATA_INTEGRITY_V2: &str = "https://w3id.org/security/data-integrity/v2";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DataIntegrityProof {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub cryptosuite: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    pub verification_method: String,
+    /// `assertionMethod` for credentials, `authentication` for presentations
+    pub proof_purpose: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub challenge: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// multibase base58btc encoded signature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof_value: Option<String>,
+}
+
+impl DataIntegrityProof {
+    fn new(key: &PatchedKeyPair, proof_purpose: &str) -> Self {
+        let fingerprint = key.fingerprint();
+        DataIntegrityProof {
+            type_: PROOF_TYPE.to_string(),
+            cryptosuite: CRYPTOSUITE.to_string(),
+            created: Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)),
+            verification_method: format!("did:key:{}#{}", fingerprint, fingerprint),
+            proof_purpose: proof_purpose.to_string(),
+            challenge: None,
+            domain: None,
+            proof_value: None,
+        }
+    }
+}
+
+/// Signs credentials with a did:key Ed25519 key pair.
+pub struct DataIntegrityIssuer {
+    key: PatchedKeyPair,
+    loader: DocumentLoader,
+}
+
+impl DataIntegrityIssuer {
+    /// Fails for keys other than Ed25519, the only keys of the cryptosuite.
+    pub fn new(key: PatchedKeyPair) -> Result<Self, &'static str> {
+        check_ed25519(&key)?;
+        Ok(DataIntegrityIssuer {
+            key,
+            loader: DocumentLoader::new(),
+        })
+    }
+
+    /// Adds a context document, like `{ "@context": { ... } }`, the credentials to sign may use.
+    pub fn context(&mut self, url: &str, document: Value) -> &mut Self {
+        self.loader.add(url, document);
+        self
+    }
+
+    /// The did:key of the issuer.
+    pub fn did(&self) -> String {
+        format!("did:key:{}", self.key.fingerprint())
+    }
+
+    /// Returns the credential with an `assertionMethod` proof.
+    pub fn sign(
+        &self,
+        credential: &VerifiableCredential,
+    ) -> Result<VerifiableCredential, &'static str> {
+        credential.validate()?;
+        if credential.issuer.id() != self.did() {
+            return Err("invalid issuer");
+        }
+        let mut credential = credential.clone();
+        if credential.version()? == DataModelVersion::V1_1 {
+            add_context(&mut credential.context);
+        }
+        let proof = DataIntegrityProof::new(&self.key, "assertionMethod");
+        let document = serde_json::to_value(credential).map_err(|_| "invalid credential")?;
+        let signed = sign_document(document, proof, &self.key, &self.loader)?;
+        serde_json::from_value(signed).map_err(|_| "invalid credential")
+    }
+
+    /// Signs the credential and builds an issue-credential message with it attached as `aries/ld-proof-vc@v1.0`.
+    pub fn issue(&self, credential: &VerifiableCredential) -> Result<Message, ProtocolError> {
+        IssueCredentialResponseBuilder::new()
+            .format(formats::LD_PROOF_VC)
+            .credential(self.sign(credential)?)
+            .build_issue_credential()
+    }
+
+    /// Returns the presentation with an `authentication` proof bound to the challenge and domain of the verifier.
+    pub fn sign_presentation(
+        &self,
+        presentation: &VerifiablePresentation,
+        challenge: &str,
+        domain: &str,
+    ) -> Result<VerifiablePresentation, &'static str> {
+        presentation.validate()?;
+        let mut presentation = presentation.clone();
+        if presentation.version()? == DataModelVersion::V1_1 {
+            add_context(&mut presentation.context);
+        }
+        let mut proof = DataIntegrityProof::new(&self.key, "authentication");
+        proof.challenge = Some(challenge.to_string());
+        proof.domain = Some(domain.to_string());
+        let document = serde_json::to_value(presentation).map_err(|_| "invalid presentation")?;
+        let signed = sign_document(document, proof, &self.key, &self.loader)?;
+        serde_json::from_value(signed).map_err(|_| "invalid presentation")
+    }
+}
+
+/// Verifies proofs offline, the keys are resolved from the did:key of the verification method.
+pub struct DataIntegrityVerifier {
+    loader: DocumentLoader,
+    /// by issuer and id
+    status_lists: HashMap<(String, String), VerifiableCredential>,
+}
+
+impl Default for DataIntegrityVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DataIntegrityVerifier {
+    pub fn new() -> Self {
+        DataIntegrityVerifier {
+            loader: DocumentLoader::new(),
+            status_lists: HashMap::new(),
+        }
+    }
+
+    /// Trusts an additional context document, like `{ "@context": { ... } }`.
+    pub fn context(&mut self, url: &str, document: Value) -> &mut Self {
+        self.loader.add(url, document);
+        self
+    }
+
+    /// Adds a status list credential the status of credentials of its issuer is checked against, replacing an older
+    /// list of the issuer with the same id.
+    pub fn status_list(
+        &mut self,
+        status_list: VerifiableCredential,
+    ) -> Result<&mut Self, &'static str> {
+        self.verify(&status_list)?;
+        let id = status_list.id.clone().ok_or("invalid status list")?;
+        self.status_lists
+            .insert((status_list.issuer.id().to_string(), id), status_list);
+        Ok(self)
+    }
+
+    /// Verifies the proof and the status of the credential.
+    pub fn verify(&self, credential: &VerifiableCredential) -> Result<(), &'static str> {
+        self.verify_proof(credential)?;
+        check_status(credential, self.status_lists.values())
+    }
+
+    /// Verifies the proof of the credential by its issuer without checking its status, like a holder receiving it.
+    pub fn verify_proof(&self, credential: &VerifiableCredential) -> Result<(), &'static str> {
+        credential.validate()?;
+        let document = serde_json::to_value(credential).map_err(|_| "invalid credential")?;
+        let proof = verify_document(document, "assertionMethod", &self.loader)?;
+        if did_of(&proof.verification_method) != credential.issuer.id() {
+            return Err("invalid verification method");
+        }
+        Ok(())
+    }
+
+    /// Verifies the proof of the presentation and of all its credentials, the presentation has to be signed for the
+    /// challenge and domain of the verifier so it can not be replayed to others.
+    pub fn verify_presentation(
+        &self,
+        presentation: &VerifiablePresentation,
+        challenge: &str,
+        domain: &str,
+    ) -> Result<(), &'static str> {
+        presentation.validate()?;
+        let document = serde_json::to_value(presentation).map_err(|_| "invalid presentation")?;
+        let proof = verify_document(document, "authentication", &self.loader)?;
+        if proof.challenge.as_deref() != Some(challenge) {
+            return Err("invalid challenge");
+        }
+        if proof.domain.as_deref() != Some(domain) {
+            return Err("invalid domain");
+        }
+        if let Some(holder) = presentation.holder.as_ref() {
+            if did_of(&proof.verification_method) != holder {
+                return Err("invalid verification method");
+            }
+        }
+        for credential in &presentation.verifiable_credential {
+            match credential {
+                PresentedCredential::Embedded(credential) => self.verify(credential)?,
+                PresentedCredential::Encoded(_) => return Err("unsupported credential format"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn did_of(verification_method: &str) -> &str {
+    verification_method.split('#').next().unwrap_or_default()
+}
+
+/// Version 1.1 documents need the data integrity context for the terms of the proof.
+fn add_context(context: &mut Vec<Value>) {
+    if !context.contains(&json!(CONTEXT_DATA_INTEGRITY_V2)) {
+        context.push(json!(CONTEXT_DATA_INTEGRITY_V2));
+    }
+}
+
+/// Hash of the proof configuration followed by the hash of the document, both canonicalized with RDFC-1.0.
+fn hash_data(
+    document: &Value,
+    proof: &DataIntegrityProof,
+    loader: &DocumentLoader,
+) -> Result<Vec<u8>, &'static str> {
+    let mut config = serde_json::to_value(proof).map_err(|_| "invalid proof")?;
+    config["@context"] = document["@context"].clone();
+    let config = canonicalize(&config, loader)?;
+    let document = canonicalize(document, loader)?;
+    let mut hash = Sha256::digest(config).to_vec();
+    hash.extend(Sha256::digest(document));
+    Ok(hash)
+}
+
+fn sign_document(
+    mut document: Value,
+    mut proof: DataIntegrityProof,
+    key: &PatchedKeyPair,
+    loader: &DocumentLoader,
+) -> Result<Value, &'static str> {
+    let object = document.as_object_mut().ok_or("invalid document")?;
+    object.remove("proof");
+    let signature = key.sign(&hash_data(&document, &proof, loader)?);
+    proof.proof_value = Some(format!("z{}", signature.to_base58()));
+    document["proof"] = serde_json::to_value(proof).map_err(|_| "invalid proof")?;
+    Ok(document)
+}
+
+fn verify_document(
+    mut document: Value,
+    proof_purpose: &str,
+    loader: &DocumentLoader,
+) -> Result<DataIntegrityProof, &'static str> {
+    let object: &mut Map<String, Value> = document.as_object_mut().ok_or("invalid document")?;
+    let proof = object.remove("proof").ok_or("no proof")?;
+    // other suites, like Ed25519Signature2018, are told apart before they fail to parse
+    if proof["type"] != PROOF_TYPE || proof["cryptosuite"] != CRYPTOSUITE {
+        return Err("unsupported proof");
+    }
+    let mut proof: DataIntegrityProof =
+        serde_json::from_value(proof).map_err(|_| "invalid proof")?;
+    if proof.proof_purpose != proof_purpose {
+        return Err("invalid proof purpose");
+    }
+    let signature = proof
+        .proof_value
+        .take()
+        .and_then(|value| value.strip_prefix('z').map(|value| value.from_base58()))
+        .ok_or("invalid proof value")?
+        .map_err(|_| "invalid proof value")?;
+    let key = did_key::resolve(did_of(&proof.verification_method))
+        .map_err(|_| "invalid verification method")?;
+    check_ed25519(&key)?;
+    key.verify(&hash_data(&document, &proof, loader)?, &signature)
+        .map_err(|_| "invalid signature")?;
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifiablecredential::DataModelVersion;
+    use base64::decode;
+    use did_key::{generate, Ed25519KeyPair, P256KeyPair};
+    use serde_json::json;
+
+    fn issuer() -> DataIntegrityIssuer {
+        DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify_credential() {
+        let issuer = issuer();
+        let credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            &issuer.did(),
+            json!({ "id": "did:example:alice", "name": "Alice" }),
+        );
+        let signed = issuer.sign(&credential).unwrap();
+        assert_eq!(signed.proof.as_ref().unwrap()["cryptosuite"], CRYPTOSUITE);
+
+        let verifier = DataIntegrityVerifier::new();
+        assert_eq!(verifier.verify(&signed), Ok(()));
+
+        let mut tampered = signed.clone();
+        tampered.credential_subject["name"] = json!("Mallory");
+        assert_eq!(verifier.verify(&tampered), Err("invalid signature"));
+        let mut unknown = signed;
+        unknown.context.push(json!("https://example.com/context"));
+        assert_eq!(verifier.verify(&unknown), Err("unknown context"));
+        assert_eq!(
+            issuer.sign(&VerifiableCredential::new(
+                DataModelVersion::V2_0,
+                "did:example:issuer",
+                json!({})
+            )),
+            Err("invalid issuer")
+        );
+    }
+
+    #[test]
+    fn test_issue_and_present() {
+        let issuer = issuer();
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V1_1,
+            &issuer.did(),
+            json!({ "id": "did:example:alice", "name": "Alice" }),
+        );
+        assert_eq!(issuer.sign(&credential), Err("undefined term"));
+        assert_eq!(
+            issuer.issue(&credential).unwrap_err(),
+            ProtocolError::Other("undefined term".to_string())
+        );
+        credential
+            .context
+            .push(json!("https://www.w3.org/ns/credentials/examples/v2"));
+        let message = issuer.issue(&credential).unwrap();
+        let message: Value = serde_json::from_str(&message.as_raw_json().unwrap()).unwrap();
+        assert_eq!(message["attachments"][0]["format"], formats::LD_PROOF_VC);
+        let signed: VerifiableCredential = serde_json::from_slice(
+            &decode(
+                message["attachments"][0]["data"]["base64"]
+                    .as_str()
+                    .unwrap(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let holder = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder"))).unwrap();
+        let presentation = holder
+            .sign_presentation(
+                &VerifiablePresentation::new(
+                    DataModelVersion::V1_1,
+                    Some(&holder.did()),
+                    vec![signed],
+                ),
+                "1234",
+                "verifier.example.com",
+            )
+            .unwrap();
+        let verifier = DataIntegrityVerifier::new();
+        assert_eq!(
+            verifier.verify_presentation(&presentation, "1234", "verifier.example.com"),
+            Ok(())
+        );
+        assert_eq!(
+            verifier.verify_presentation(&presentation, "5678", "verifier.example.com"),
+            Err("invalid challenge")
+        );
+        assert_eq!(
+            verifier.verify_presentation(&presentation, "1234", "other.example.com"),
+            Err("invalid domain")
+        );
+        assert_eq!(
+            DataIntegrityIssuer::new(generate::<P256KeyPair>(Some(b"holder"))).err(),
+            Some("unsupported key")
+        );
+    }
+}
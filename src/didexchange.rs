@@ -3,6 +3,8 @@
 //! Protocol to exchange DIDs between agents when establishing a DID based relationship.
 //! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0023-did-exchange/README.md>
 
+use crate::connection::Connection;
+use crate::headers;
 use didcomm_rs::Message;
 use serde_json::Value;
 use uuid::Uuid;
@@ -12,6 +14,9 @@ pub struct DidExchangeResponseBuilder {
     did: Option<String>,
     message: Option<Message>,
     did_doc: Option<Value>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
 }
 
 impl DidExchangeResponseBuilder {
@@ -34,6 +39,21 @@ impl DidExchangeResponseBuilder {
         self
     }
 
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
     pub fn build(&mut self) -> Result<Message, &'static str> {
         match &self.message {
             Some(message) => match message.get_didcomm_header().m_type.as_str() {
@@ -50,12 +70,21 @@ impl DidExchangeResponseBuilder {
         }
     }
 
+    /// Creates the connection from the request or response the other party sent.
+    pub fn build_connection(&mut self) -> Result<Connection, &'static str> {
+        match (&self.did, &self.message) {
+            (Some(did), Some(message)) => Connection::from_exchange(did.to_string(), message),
+            (None, _) => Err("no did"),
+            (_, None) => Err("no message"),
+        }
+    }
+
     pub fn build_request(&mut self) -> Result<Message, &'static str> {
         let thid = match &self.message {
             Some(message) => message.get_didcomm_header().id.clone(),
             _ => Uuid::new_v4().to_string(),
         };
-        Ok(Message::new()
+        let message = Message::new()
             .m_type("https://didcomm.org/didexchange/1.0/request")
             .thid(&thid)
             .pthid(&thid)
@@ -64,11 +93,13 @@ impl DidExchangeResponseBuilder {
             .add_header_field(
                 "did_doc~attach".to_string(),
                 serde_json::to_string_pretty(&self.did_doc.clone().unwrap()).unwrap(),
-            ))
+            );
+        let message = headers::timed(message, self.created_time, self.expires_time)?;
+        Ok(headers::please_ack(message, self.please_ack))
     }
 
     pub fn build_response(&mut self) -> Result<Message, &'static str> {
-        Ok(Message::new()
+        let message = Message::new()
             .m_type("https://didcomm.org/didexchange/1.0/response")
             .thid(&self.message.as_ref().unwrap().get_didcomm_header().id)
             .pthid(&self.message.as_ref().unwrap().get_didcomm_header().id)
@@ -76,11 +107,13 @@ impl DidExchangeResponseBuilder {
             .add_header_field(
                 "did_doc~attach".to_string(),
                 serde_json::to_string_pretty(&self.did_doc.clone().unwrap()).unwrap(),
-            ))
+            );
+        let message = headers::timed(message, self.created_time, self.expires_time)?;
+        Ok(headers::please_ack(message, self.please_ack))
     }
 
     pub fn build_complete(&mut self) -> Result<Message, &'static str> {
-        Ok(Message::new()
+        let message = Message::new()
             .m_type("https://didcomm.org/didexchange/1.0/complete")
             .thid(
                 self.message
@@ -92,7 +125,9 @@ impl DidExchangeResponseBuilder {
                     .unwrap()
                     .as_str(),
             )
-            .pthid(&self.message.as_ref().unwrap().get_didcomm_header().id))
+            .pthid(&self.message.as_ref().unwrap().get_didcomm_header().id);
+        let message = headers::timed(message, self.created_time, self.expires_time)?;
+        Ok(headers::please_ack(message, self.please_ack))
     }
 }
 
@@ -207,7 +242,7 @@ mod tests {
         );
 
         let complete = DidExchangeResponseBuilder::new()
-            .message(response)
+            .message(response.clone())
             .did("did:key:z6MkpFZ86WuUpihn1mTRbpBCGE6YpCvsBYtZQYnd9jcuAUup".to_string())
             .build()
             .unwrap();
@@ -217,6 +252,13 @@ mod tests {
             "https://didcomm.org/didexchange/1.0/complete"
         );
 
+        let connection = DidExchangeResponseBuilder::new()
+            .message(response)
+            .did("did:key:z6MkpFZ86WuUpihn1mTRbpBCGE6YpCvsBYtZQYnd9jcuAUup".to_string())
+            .build_connection()
+            .unwrap();
+        assert!(connection.is_completed());
+
         println!("{}", serde_json::to_string_pretty(&complete).unwrap());
     }
 }
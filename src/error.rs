@@ -0,0 +1,172 @@
+//! # Error
+//!
+//! Errors of the protocols and their conversion to problem reports, so the other party learns why a message failed.
+//! <https://identity.foundation/didcomm-messaging/spec/#problem-reports>
+
+use crate::reportproblem::{descriptors, ProblemCode, ReportProblemResponseBuilder, Scope, Sorter};
+use didcomm_rs::Message;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    /// The message type is not supported, contains the type.
+    UnsupportedMessage(String),
+    /// The message is not expected in the current state of the protocol, contains the state.
+    BadState(String),
+    InvalidSignature,
+    Expired,
+    /// Data does not match the schema or the offer, contains the reason.
+    SchemaMismatch(String),
+    Other(String),
+}
+
+impl ProtocolError {
+    pub fn problem_code(&self) -> ProblemCode {
+        match self {
+            ProtocolError::UnsupportedMessage(_) => {
+                ProblemCode::new(Sorter::Error, Scope::Message, "msg.unsupported")
+            }
+            ProtocolError::BadState(_) => {
+                ProblemCode::new(Sorter::Error, Scope::Protocol, "msg.bad-state")
+            }
+            ProtocolError::InvalidSignature => {
+                ProblemCode::new(Sorter::Error, Scope::Protocol, descriptors::TRUST_CRYPTO)
+            }
+            ProtocolError::Expired => {
+                ProblemCode::new(Sorter::Error, Scope::Message, descriptors::REQ_TIME)
+            }
+            ProtocolError::SchemaMismatch(_) => {
+                ProblemCode::new(Sorter::Error, Scope::Protocol, "msg.schema-mismatch")
+            }
+            ProtocolError::Other(_) => {
+                ProblemCode::new(Sorter::Error, Scope::Protocol, descriptors::ME)
+            }
+        }
+    }
+
+    fn comment(&self) -> (String, Vec<String>) {
+        match self {
+            ProtocolError::UnsupportedMessage(m_type) => (
+                "Message type {1} is not supported.".to_string(),
+                vec![m_type.to_string()],
+            ),
+            ProtocolError::BadState(state) => (
+                "Message is not expected in state {1}.".to_string(),
+                vec![state.to_string()],
+            ),
+            ProtocolError::InvalidSignature => ("Signature is invalid.".to_string(), vec![]),
+            ProtocolError::Expired => ("Message expired.".to_string(), vec![]),
+            ProtocolError::SchemaMismatch(reason) => (
+                "Credential does not match the offer: {1}".to_string(),
+                vec![reason.to_string()],
+            ),
+            ProtocolError::Other(reason) => ("{1}".to_string(), vec![reason.to_string()]),
+        }
+    }
+
+    /// Builds the problem report about the message that failed.
+    ///
+    /// A report on a failed protocol starts its own thread with the thread of the protocol as parent,
+    /// a report on a single message continues the thread of the message.
+    pub fn report(&self, message: &Message) -> Result<Message, &'static str> {
+        let header = message.get_didcomm_header();
+        let thid = header.thid.clone().unwrap_or_else(|| header.id.clone());
+        let error = match self {
+            ProtocolError::UnsupportedMessage(m_type) if m_type.is_empty() => {
+                ProtocolError::UnsupportedMessage(header.m_type.clone())
+            }
+            error => error.clone(),
+        };
+        let code = error.problem_code();
+        let (comment, args) = error.comment();
+
+        let mut builder = ReportProblemResponseBuilder::new();
+        builder
+            .comment(comment)
+            .args(args)
+            .ack(vec![header.id.clone()]);
+        if code.aborts_protocol() {
+            builder.pthid(thid);
+        } else {
+            builder.thid(thid);
+        }
+        builder.problem_code(code).build()
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::UnsupportedMessage(m_type) => {
+                write!(f, "unsupported message {}", m_type)
+            }
+            ProtocolError::BadState(state) => write!(f, "bad state {}", state),
+            ProtocolError::InvalidSignature => write!(f, "invalid signature"),
+            ProtocolError::Expired => write!(f, "message expired"),
+            ProtocolError::SchemaMismatch(reason) => write!(f, "schema mismatch {}", reason),
+            ProtocolError::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Errors of the protocols that are not about the schema, state or signature.
+impl From<&'static str> for ProtocolError {
+    fn from(error: &'static str) -> Self {
+        ProtocolError::Other(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reportproblem::ProblemReport;
+    use crate::TrustPingResponseBuilder;
+
+    #[test]
+    fn test_report_expired_ping() {
+        let ping = TrustPingResponseBuilder::new()
+            .expires_time(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            TrustPingResponseBuilder::new()
+                .message(ping.clone())
+                .build()
+                .unwrap_err(),
+            "message expired"
+        );
+
+        let report = ProtocolError::Expired.report(&ping).unwrap();
+        assert_eq!(
+            report.get_didcomm_header().thid.as_ref(),
+            Some(&ping.get_didcomm_header().id)
+        );
+        assert_eq!(report.get_didcomm_header().pthid, None);
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert_eq!(report.code.to_string(), "e.m.req.time");
+        assert_eq!(report.render().unwrap(), "Message expired.");
+    }
+
+    #[test]
+    fn test_report_protocol_failure() {
+        let message = Message::new().thid("exchange");
+        let report = ProtocolError::BadState("completed".to_string())
+            .report(&message)
+            .unwrap();
+        assert_eq!(
+            report.get_didcomm_header().pthid.as_deref(),
+            Some("exchange")
+        );
+        assert_eq!(report.get_didcomm_header().thid, None);
+
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert!(report.code.aborts_protocol());
+        assert_eq!(report.code.to_string(), "e.p.msg.bad-state");
+        assert_eq!(
+            report.render().unwrap(),
+            "Message is not expected in state completed."
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! # Headers
+//!
+//! The `ack` and `please_ack` headers are lists of message ids. Header fields of didcomm-rs messages can only hold
+//! strings, so the lists are kept as JSON encoded arrays in them and turned into JSON arrays on the wire: messages are
+//! serialized with [`as_raw_json`] and received messages are read with [`from_raw_json`].
+//! <https://identity.foundation/didcomm-messaging/spec/#message-headers>
+
+use didcomm_rs::Message;
+use serde_json::{json, Value};
+
+/// Headers holding a list of message ids.
+pub const LIST_HEADERS: [&str; 2] = ["ack", "please_ack"];
+
+/// Serializes the message with the list headers as JSON arrays.
+pub fn as_raw_json(message: &Message) -> Result<String, &'static str> {
+    let mut fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+    for name in LIST_HEADERS {
+        if fields.get(name).is_some() {
+            fields[name] = json!(get_list(message, name));
+        }
+    }
+    serde_json::to_string(&fields).map_err(|_| "invalid message")
+}
+
+/// Parses a message with list headers as JSON arrays, which didcomm-rs can not read.
+pub fn from_raw_json(json: &str) -> Result<Message, &'static str> {
+    let mut fields: Value = serde_json::from_str(json).map_err(|_| "invalid message")?;
+    for name in LIST_HEADERS {
+        if let Some(list) = fields.get_mut(name).filter(|list| list.is_array()) {
+            *list = Value::String(list.to_string());
+        }
+    }
+    serde_json::from_value(fields).map_err(|_| "invalid message")
+}
+
+pub(crate) fn add_list(message: Message, name: &str, values: &[String]) -> Message {
+    message.add_header_field(name.to_string(), serde_json::to_string(values).unwrap())
+}
+
+/// Reads a list header, accepting a JSON array as well as an array encoded as string.
+pub(crate) fn get_list(message: &Message, name: &str) -> Vec<String> {
+    let fields = serde_json::to_value(message).unwrap_or_default();
+    match &fields[name] {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(String::from))
+            .collect(),
+        Value::String(value) => {
+            serde_json::from_str(value).unwrap_or_else(|_| vec![value.to_string()])
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Sets the `created_time` and `expires_time` headers, both in seconds since Unix Epoch.
+pub(crate) fn timed(
+    message: Message,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+) -> Result<Message, &'static str> {
+    if created_time.is_none() && expires_time.is_none() {
+        return Ok(message);
+    }
+    let mut fields = serde_json::to_value(&message).map_err(|_| "invalid message")?;
+    if let Some(created_time) = created_time {
+        fields["created_time"] = created_time.into();
+    }
+    if let Some(expires_time) = expires_time {
+        fields["expires_time"] = expires_time.into();
+    }
+    serde_json::from_value(fields).map_err(|_| "invalid message")
+}
+
+/// Asks the recipient to acknowledge the message itself.
+pub(crate) fn please_ack(message: Message, please_ack: bool) -> Message {
+    if !please_ack {
+        return message;
+    }
+    let id = message.get_didcomm_header().id.clone();
+    add_list(message, "please_ack", &[id])
+}
+
+/// Reads a header field holding a string.
+pub(crate) fn get(message: &Message, name: &str) -> Option<String> {
+    let fields = serde_json::to_value(message).ok()?;
+    fields[name].as_str().map(String::from)
+}
@@ -0,0 +1,359 @@
+//! # Health Monitor
+//!
+//! Keeps track of the health of connections using the trust-ping protocol. The monitor does not schedule anything itself,
+//! the caller asks which connections are due a ping, sends the pings and passes back responses and the current time.
+//! <https://identity.foundation/didcomm-messaging/spec/#trust-ping-protocol-20>
+
+use crate::connection::Connection;
+use crate::trustping::{PingTracker, TrustPingResponseBuilder};
+use chrono::{DateTime, Duration, Utc};
+use didcomm_rs::Message;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthStatus {
+    Healthy,
+    /// Some pings were not answered in time.
+    Degraded,
+    /// Too many pings in a row were not answered.
+    Unreachable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthEvent {
+    PingAnswered {
+        connection_id: String,
+        round_trip: Duration,
+    },
+    PingTimedOut {
+        connection_id: String,
+    },
+    StatusChanged {
+        connection_id: String,
+        from: HealthStatus,
+        to: HealthStatus,
+    },
+}
+
+struct ConnectionHealth {
+    connection: Connection,
+    status: HealthStatus,
+    failures: u32,
+    next_ping: DateTime<Utc>,
+    last_round_trip: Option<Duration>,
+}
+
+pub struct HealthMonitor {
+    interval: Duration,
+    max_interval: Duration,
+    timeout: Duration,
+    unreachable_after: u32,
+    connections: HashMap<String, ConnectionHealth>,
+    pings: HashMap<String, String>,
+    tracker: PingTracker,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        HealthMonitor {
+            interval: Duration::seconds(60),
+            max_interval: Duration::hours(1),
+            timeout: Duration::seconds(30),
+            unreachable_after: 3,
+            connections: HashMap::new(),
+            pings: HashMap::new(),
+            tracker: PingTracker::new(),
+        }
+    }
+
+    /// Time between pings of a healthy connection.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Upper bound of the backoff after failed pings.
+    pub fn max_interval(&mut self, max_interval: Duration) -> &mut Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// Time to wait for a ping-response before the ping counts as failed.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of failed pings in a row after which a connection is unreachable.
+    pub fn unreachable_after(&mut self, unreachable_after: u32) -> &mut Self {
+        self.unreachable_after = unreachable_after;
+        self
+    }
+
+    /// Starts monitoring the connection, it is due a ping right away.
+    pub fn add(&mut self, connection: Connection, now: DateTime<Utc>) -> &mut Self {
+        self.connections.insert(
+            connection.id.clone(),
+            ConnectionHealth {
+                connection,
+                status: HealthStatus::Healthy,
+                failures: 0,
+                next_ping: now,
+                last_round_trip: None,
+            },
+        );
+        self
+    }
+
+    pub fn remove(&mut self, connection_id: &str) -> Option<Connection> {
+        let pings: Vec<String> = self
+            .pings
+            .iter()
+            .filter(|(_, id)| id.as_str() == connection_id)
+            .map(|(ping, _)| ping.to_string())
+            .collect();
+        for ping in pings {
+            self.pings.remove(&ping);
+            self.tracker.forget(&ping);
+        }
+        self.connections
+            .remove(connection_id)
+            .map(|health| health.connection)
+    }
+
+    pub fn status(&self, connection_id: &str) -> Option<HealthStatus> {
+        self.connections
+            .get(connection_id)
+            .map(|health| health.status)
+    }
+
+    pub fn last_round_trip(&self, connection_id: &str) -> Option<Duration> {
+        self.connections
+            .get(connection_id)
+            .and_then(|health| health.last_round_trip)
+    }
+
+    /// Ids of connections due a ping that are not waiting for a response.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.connections
+            .iter()
+            .filter(|(id, health)| {
+                health.next_ping <= now && !self.pings.values().any(|ping| &ping == id)
+            })
+            .map(|(id, _)| id.to_string())
+            .collect()
+    }
+
+    /// Builds a ping for the connection and waits for its response.
+    pub fn ping(
+        &mut self,
+        connection_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Message, &'static str> {
+        let health = self
+            .connections
+            .get(connection_id)
+            .ok_or("unknown connection")?;
+        let ping = TrustPingResponseBuilder::new()
+            .connection(health.connection.clone())
+            .expires_time((now + self.timeout).timestamp() as u64)
+            .build_ping()?;
+        self.tracker.track_at(&ping, now);
+        self.pings.insert(
+            ping.get_didcomm_header().id.clone(),
+            connection_id.to_string(),
+        );
+        Ok(ping)
+    }
+
+    pub fn receive(
+        &mut self,
+        response: &Message,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<HealthEvent>, &'static str> {
+        let round_trip = self.tracker.receive_at(response, now)?;
+        let thid = response.get_didcomm_header().thid.as_ref().unwrap();
+        let connection_id = self.pings.remove(thid).ok_or("unknown ping")?;
+        let interval = self.interval;
+        let health = self
+            .connections
+            .get_mut(&connection_id)
+            .ok_or("unknown connection")?;
+        health.failures = 0;
+        health.next_ping = now + interval;
+        health.last_round_trip = Some(round_trip);
+
+        let mut events = vec![HealthEvent::PingAnswered {
+            connection_id: connection_id.to_string(),
+            round_trip,
+        }];
+        events.extend(Self::update_status(
+            &connection_id,
+            health,
+            self.unreachable_after,
+        ));
+        Ok(events)
+    }
+
+    /// Counts pings without response for longer than the timeout as failed and backs off.
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<HealthEvent> {
+        let mut events = Vec::new();
+        for ping in self.tracker.unanswered_at(self.timeout, now) {
+            self.tracker.forget(&ping);
+            let connection_id = match self.pings.remove(&ping) {
+                Some(connection_id) => connection_id,
+                None => continue,
+            };
+            let (interval, max_interval) = (self.interval, self.max_interval);
+            if let Some(health) = self.connections.get_mut(&connection_id) {
+                health.failures += 1;
+                health.next_ping = now + Self::backoff(interval, max_interval, health.failures);
+                events.push(HealthEvent::PingTimedOut {
+                    connection_id: connection_id.to_string(),
+                });
+                events.extend(Self::update_status(
+                    &connection_id,
+                    health,
+                    self.unreachable_after,
+                ));
+            }
+        }
+        events
+    }
+
+    /// Doubles the interval with every failure, up to the maximum interval.
+    fn backoff(interval: Duration, max_interval: Duration, failures: u32) -> Duration {
+        let factor = 2i32.saturating_pow(failures.min(30));
+        match interval.checked_mul(factor) {
+            Some(backoff) if backoff <= max_interval => backoff,
+            _ => max_interval,
+        }
+    }
+
+    fn update_status(
+        connection_id: &str,
+        health: &mut ConnectionHealth,
+        unreachable_after: u32,
+    ) -> Option<HealthEvent> {
+        let status = match health.failures {
+            0 => HealthStatus::Healthy,
+            failures if failures < unreachable_after => HealthStatus::Degraded,
+            _ => HealthStatus::Unreachable,
+        };
+        if status == health.status {
+            return None;
+        }
+        let event = HealthEvent::StatusChanged {
+            connection_id: connection_id.to_string(),
+            from: health.status,
+            to: status,
+        };
+        health.status = status;
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ConnectionState;
+
+    fn connection() -> Connection {
+        Connection {
+            id: "42".to_string(),
+            did: "did:key:alice".to_string(),
+            their_did: "did:key:bob".to_string(),
+            their_services: Vec::new(),
+            their_keys: Vec::new(),
+            invitation_id: None,
+            state: ConnectionState::Completed,
+            created_time: 0,
+            updated_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_ping_answered() {
+        let now = Utc::now();
+        let mut monitor = HealthMonitor::new();
+        monitor.add(connection(), now);
+        assert_eq!(monitor.due(now), vec!["42".to_string()]);
+
+        let ping = monitor.ping("42", now).unwrap();
+        assert!(monitor.due(now).is_empty());
+        assert_eq!(
+            ping.get_didcomm_header().to,
+            vec!["did:key:bob".to_string()]
+        );
+
+        let response = TrustPingResponseBuilder::new()
+            .message(ping)
+            .build()
+            .unwrap();
+        let events = monitor
+            .receive(&response, now + Duration::milliseconds(20))
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![HealthEvent::PingAnswered {
+                connection_id: "42".to_string(),
+                round_trip: Duration::milliseconds(20)
+            }]
+        );
+        assert!(monitor.due(now + Duration::seconds(30)).is_empty());
+        assert_eq!(monitor.due(now + Duration::seconds(61)).len(), 1);
+    }
+
+    #[test]
+    fn test_backoff_and_status() {
+        let mut now = Utc::now();
+        let mut monitor = HealthMonitor::new();
+        monitor
+            .interval(Duration::seconds(10))
+            .timeout(Duration::seconds(5))
+            .unreachable_after(2)
+            .add(connection(), now);
+
+        monitor.ping("42", now).unwrap();
+        now += Duration::seconds(6);
+        let events = monitor.check(now);
+        assert_eq!(monitor.status("42"), Some(HealthStatus::Degraded));
+        assert_eq!(events.len(), 2);
+        assert!(monitor.due(now + Duration::seconds(19)).is_empty());
+        assert_eq!(monitor.due(now + Duration::seconds(20)).len(), 1);
+
+        now += Duration::seconds(20);
+        let ping = monitor.ping("42", now).unwrap();
+        now += Duration::seconds(6);
+        monitor.check(now);
+        assert_eq!(monitor.status("42"), Some(HealthStatus::Unreachable));
+        assert!(monitor.due(now + Duration::seconds(39)).is_empty());
+
+        let response = TrustPingResponseBuilder::new()
+            .message(ping)
+            .build_response()
+            .unwrap();
+        assert!(monitor.receive(&response, now).is_err());
+        assert!(monitor.remove("42").is_some());
+        assert_eq!(monitor.status("42"), None);
+    }
+
+    #[test]
+    fn test_backoff_clamped() {
+        let max_interval = Duration::hours(1);
+        assert_eq!(
+            HealthMonitor::backoff(Duration::seconds(30), max_interval, 2),
+            Duration::minutes(2)
+        );
+        assert_eq!(
+            HealthMonitor::backoff(Duration::days(365_000), max_interval, 30),
+            max_interval
+        );
+    }
+}
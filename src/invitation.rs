@@ -3,6 +3,7 @@
 //! The out-of-band protocol consists in a single message that is sent by the sender.
 //! <https://identity.foundation/didcomm-messaging/spec/#invitation>
 
+use crate::headers;
 use crate::service::Service;
 use didcomm_rs::{AttachmentBuilder, AttachmentDataBuilder, Message};
 use serde_json::{json, Value};
@@ -15,22 +16,36 @@ pub enum GoalCode {
 
 #[derive(Default)]
 pub struct InvitationBuilder {
+    did: Option<String>,
     goal_code: Option<String>,
     goal: Option<String>,
     attachments: Option<Vec<Message>>,
     services: Option<Vec<Service>>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
 }
 
 impl InvitationBuilder {
     pub fn new() -> Self {
         InvitationBuilder {
+            did: None,
             goal: None,
             goal_code: None,
             attachments: None,
             services: None,
+            created_time: None,
+            expires_time: None,
+            please_ack: false,
         }
     }
 
+    /// DID of the sender, the invitee replies to it.
+    pub fn did(&mut self, did: String) -> &mut Self {
+        self.did = Some(did);
+        self
+    }
+
     pub fn goal(&mut self, goal: String) -> &mut Self {
         self.goal = Some(goal);
         self
@@ -55,10 +70,28 @@ impl InvitationBuilder {
         self
     }
 
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
     pub fn build(&mut self) -> Result<Message, &'static str> {
         let mut message = Message::new()
             .m_type("https://didcomm.org/out-of-band/2.0/invitation")
             .body(&serde_json::to_string(&self.build_body()).unwrap());
+        if let Some(did) = self.did.as_ref() {
+            message = message.from(did);
+        }
 
         if self.attachments.is_some() {
             for attachment in self.attachments.as_ref().unwrap() {
@@ -79,6 +112,8 @@ impl InvitationBuilder {
                 serde_json::to_string(&self.services).unwrap(),
             );
         }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
 
         Ok(message)
     }
@@ -4,23 +4,91 @@
 //! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0453-issue-credential-v2/README.md>
 //! ![](https://github.com/hyperledger/aries-rfcs/raw/main/features/0453-issue-credential-v2/credential-issuance.png)
 
-use base64::encode;
+use crate::connection::Connection;
+use crate::credentialmanifest::CredentialManifest;
+use crate::error::ProtocolError;
+use crate::headers;
+use crate::v3message::payload;
+use crate::verifiablecredential::{VerifiableCredential, VerifiablePresentation};
+use base64::{decode, decode_config, encode, encode_config, URL_SAFE_NO_PAD};
 use didcomm_rs::{AttachmentBuilder, AttachmentDataBuilder, Message};
+use jsonschema::JSONSchema;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Map, Value};
+
+/// Attachment formats of credentials
+/// <https://github.com/hyperledger/aries-rfcs/blob/main/features/0453-issue-credential-v2/README.md#credential-attachment-formats>
+pub mod formats {
+    pub const LD_PROOF_VC: &str = "aries/ld-proof-vc@v1.0";
+    pub const JWT_VC: &str = "jwt_vc_json";
+    pub const SD_JWT_VC: &str = "vc+sd-jwt";
+    /// DIF Credential Manifest of an offer, used by WACI DIDComm
+    pub const CREDENTIAL_MANIFEST: &str = "dif/credential-manifest/manifest@v1.0";
+    pub const CREDENTIAL_APPLICATION: &str = "dif/credential-manifest/application@v1.0";
+    pub const CREDENTIAL_FULFILLMENT: &str = "dif/credential-manifest/fulfillment@v1.0";
+    pub const CREDENTIAL_RESPONSE: &str = "dif/credential-manifest/response@v1.0";
+
+    /// Formats of the Credential Manifest exchange, attached next to credentials but not credentials themselves.
+    pub(crate) const MANIFEST_FORMATS: &[&str] = &[
+        CREDENTIAL_MANIFEST,
+        CREDENTIAL_APPLICATION,
+        CREDENTIAL_FULFILLMENT,
+        CREDENTIAL_RESPONSE,
+    ];
+}
 
 // https://github.com/hyperledger/aries-rfcs/blob/main/features/0453-issue-credential-v2/README.md#preview-credential
-#[derive(Serialize, Deserialize, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct CredentialPreview {
     #[serde(rename = "type")]
     pub type_: String,
     pub attributes: Vec<CredentialAttribute>,
 }
 
-// if mime-type is not null, then value is always a base64url-encoded string that represents a binary BLOB, and mime-type tells how to interpret the BLOB after base64url-decoding.
+impl CredentialPreview {
+    /// The attributes as object of names and values.
+    pub fn claims(&self) -> Value {
+        Value::Object(
+            self.attributes
+                .iter()
+                .map(|attribute| (attribute.name.to_string(), json!(attribute.value)))
+                .collect(),
+        )
+    }
+
+    /// Checks the attributes against a JSON schema of the claims.
+    pub fn validate(&self, schema: &Value) -> Result<(), ProtocolError> {
+        validate_claims(schema, &self.claims())
+    }
+
+    /// Checks the subject of the credential holds exactly the attributes of the preview.
+    pub fn check_credential(&self, credential: &VerifiableCredential) -> Result<(), ProtocolError> {
+        let mut claims = subject_claims(credential)?;
+        for attribute in &self.attributes {
+            if claims.remove(&attribute.name) != Some(json!(attribute.value)) {
+                return Err(ProtocolError::SchemaMismatch(format!(
+                    "attribute {} differs",
+                    attribute.name
+                )));
+            }
+        }
+        match claims.keys().next() {
+            Some(name) => Err(ProtocolError::SchemaMismatch(format!(
+                "attribute {} was not offered",
+                name
+            ))),
+            None => Ok(()),
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+/// Largest binary attribute accepted, in bytes.
+pub const MAX_BINARY_SIZE: usize = 1024 * 1024;
+
+/// If mime-type is not null, then value is always a base64url-encoded string that represents a binary BLOB, and
+/// mime-type tells how to interpret the BLOB after base64url-decoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 pub struct CredentialAttribute {
     pub name: String,
     #[serde(rename = "mime-type")]
@@ -36,6 +104,69 @@ impl CredentialAttribute {
             mime_type: None,
         }
     }
+
+    /// Creates a binary attribute like a photo or a document, at most [MAX_BINARY_SIZE] bytes.
+    pub fn binary(name: String, mime_type: &str, data: &[u8]) -> Result<Self, &'static str> {
+        Self::binary_with_limit(name, mime_type, data, MAX_BINARY_SIZE)
+    }
+
+    pub fn binary_with_limit(
+        name: String,
+        mime_type: &str,
+        data: &[u8],
+        max_size: usize,
+    ) -> Result<Self, &'static str> {
+        validate_mime_type(mime_type)?;
+        if data.len() > max_size {
+            return Err("attribute too large");
+        }
+        Ok(CredentialAttribute {
+            name,
+            mime_type: Some(mime_type.to_string()),
+            value: encode_config(data, URL_SAFE_NO_PAD),
+        })
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.mime_type.is_some()
+    }
+
+    /// The decoded value of a binary attribute.
+    pub fn bytes(&self) -> Result<Vec<u8>, &'static str> {
+        if !self.is_binary() {
+            return Err("no binary attribute");
+        }
+        decode_config(self.value.trim_end_matches('='), URL_SAFE_NO_PAD)
+            .map_err(|_| "invalid binary attribute")
+    }
+
+    /// Checks the mime type, encoding and size of binary attributes.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let Some(mime_type) = self.mime_type.as_ref() {
+            validate_mime_type(mime_type)?;
+            if self.bytes()?.len() > MAX_BINARY_SIZE {
+                return Err("attribute too large");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Accepts `type/subtype` with the characters allowed by RFC 6838 and optional parameters.
+fn validate_mime_type(mime_type: &str) -> Result<(), &'static str> {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    let valid_name = |name: &str| {
+        !name.is_empty()
+            && name.len() <= 127
+            && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match essence.split_once('/') {
+        Some((type_, subtype)) if valid_name(type_) && valid_name(subtype) => Ok(()),
+        _ => Err("invalid mime type"),
+    }
 }
 
 /// Issue Credential Response Builder
@@ -63,6 +194,16 @@ pub struct IssueCredentialResponseBuilder {
     message: Option<Message>,
     replacement_id: Option<String>,
     attachments: Vec<Value>,
+    credentials: Vec<VerifiableCredential>,
+    format: Option<String>,
+    credential_schema: Option<Value>,
+    credential_manifest: Option<CredentialManifest>,
+    credential_application: Option<VerifiablePresentation>,
+    credential_response: Option<VerifiablePresentation>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
 }
 
 impl IssueCredentialResponseBuilder {
@@ -110,27 +251,93 @@ impl IssueCredentialResponseBuilder {
         self
     }
 
-    pub fn build(&mut self) -> Result<Message, &'static str> {
+    /// Attaches the credential, it is validated when the message is built.
+    pub fn credential(&mut self, credential: VerifiableCredential) -> &mut Self {
+        self.credentials.push(credential);
+        self
+    }
+
+    /// Format of the attached credentials, see [formats]. Strings are attached as they are with a format, like JWTs.
+    pub fn format(&mut self, format: &str) -> &mut Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    /// JSON schema of the claims, sent with the offer and checked against preview and credentials.
+    pub fn credential_schema(&mut self, credential_schema: Value) -> &mut Self {
+        self.credential_schema = Some(credential_schema);
+        self
+    }
+
+    /// Attaches the manifest to the offer, telling the holder what it receives and has to present.
+    pub fn credential_manifest(&mut self, credential_manifest: CredentialManifest) -> &mut Self {
+        self.credential_manifest = Some(credential_manifest);
+        self
+    }
+
+    /// Attaches the presentation with the embedded credential application to the request.
+    pub fn credential_application(
+        &mut self,
+        credential_application: VerifiablePresentation,
+    ) -> &mut Self {
+        self.credential_application = Some(credential_application);
+        self
+    }
+
+    /// Attaches the presentation with the embedded credential response to the issued credentials.
+    pub fn credential_response(
+        &mut self,
+        credential_response: VerifiablePresentation,
+    ) -> &mut Self {
+        self.credential_response = Some(credential_response);
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build(&mut self) -> Result<Message, ProtocolError> {
         match &self.message {
             Some(message) => match message.get_didcomm_header().m_type.as_str() {
                 "https://didcomm.org/issue-credential/2.1/offer-credential" => {
                     self.build_propose_credential()
                 }
-                _ => {
-                    println!("{}", message.get_didcomm_header().m_type.as_str());
-                    Err("unsupported message")
-                }
+                m_type => Err(ProtocolError::UnsupportedMessage(m_type.to_string())),
             },
-            None => Err("no message"),
+            None => Err("no message".into()),
         }
     }
 
-    pub fn build_propose_credential(&mut self) -> Result<Message, &'static str> {
+    pub fn build_propose_credential(&mut self) -> Result<Message, ProtocolError> {
+        let credential_preview = self
+            .credential_preview
+            .as_ref()
+            .ok_or("no credential preview")?;
+        for attribute in &credential_preview.attributes {
+            attribute.validate()?;
+        }
         let mut message = Message::new()
             .m_type("https://didcomm.org/issue-credential/2.1/propose-credential")
             .add_header_field(
                 "credential_preview".to_string(),
-                serde_json::to_string(&self.credential_preview.as_ref().unwrap()).unwrap(),
+                serde_json::to_string(credential_preview).unwrap(),
             );
         if let Some(comment) = self.comment.as_ref() {
             message = message.add_header_field("comment".to_string(), comment.to_string())
@@ -138,51 +345,275 @@ impl IssueCredentialResponseBuilder {
         if let Some(goal_code) = self.goal_code.as_ref() {
             message = message.add_header_field("goal_code".to_string(), goal_code.to_string())
         }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
         Ok(message)
     }
 
-    pub fn build_offer_credential(&mut self) -> Result<Message, &'static str> {
-        let mut message = Message::new()
-            .m_type("https://didcomm.org/issue-credential/2.1/offer-credential")
-            .add_header_field(
-                "credential_preview".to_string(),
-                serde_json::to_string(&self.credential_preview.as_ref().unwrap()).unwrap(),
+    /// Builds an offer with a credential preview, a credential manifest or both.
+    pub fn build_offer_credential(&mut self) -> Result<Message, ProtocolError> {
+        let mut message =
+            Message::new().m_type("https://didcomm.org/issue-credential/2.1/offer-credential");
+        match self.credential_preview.as_ref() {
+            Some(credential_preview) => {
+                for attribute in &credential_preview.attributes {
+                    attribute.validate()?;
+                }
+                message = message.add_header_field(
+                    "credential_preview".to_string(),
+                    serde_json::to_string(credential_preview).unwrap(),
+                );
+                if let Some(credential_schema) = self.credential_schema.as_ref() {
+                    credential_preview.validate(credential_schema)?;
+                    message = message.add_header_field(
+                        "credential_schema".to_string(),
+                        credential_schema.to_string(),
+                    );
+                }
+            }
+            None if self.credential_manifest.is_none() => {
+                return Err("no credential preview".into())
+            }
+            None => {}
+        }
+        if let Some(credential_manifest) = self.credential_manifest.as_ref() {
+            message = attach(
+                message,
+                "credential-manifest",
+                Some(formats::CREDENTIAL_MANIFEST),
+                json!(credential_manifest).to_string(),
             );
+        }
+
+        if let Some(comment) = self.comment.as_ref() {
+            message = message.add_header_field("comment".to_string(), comment.to_string())
+        }
+        if let Some(goal_code) = self.goal_code.as_ref() {
+            message = message.add_header_field("goal_code".to_string(), goal_code.to_string())
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
 
+    /// Builds a request with a credential preview, a credential application or both.
+    pub fn build_request_credential(&mut self) -> Result<Message, ProtocolError> {
+        let mut message =
+            Message::new().m_type("https://didcomm.org/issue-credential/2.1/request-credential");
+        match self.credential_preview.as_ref() {
+            Some(credential_preview) => {
+                for attribute in &credential_preview.attributes {
+                    attribute.validate()?;
+                }
+                message = message.add_header_field(
+                    "credential_preview".to_string(),
+                    serde_json::to_string(credential_preview).unwrap(),
+                );
+            }
+            None if self.credential_application.is_none() => {
+                return Err("no credential preview".into())
+            }
+            None => {}
+        }
+        if let Some(credential_application) = self.credential_application.as_ref() {
+            credential_application.validate()?;
+            message = attach(
+                message,
+                "credential-application",
+                Some(formats::CREDENTIAL_APPLICATION),
+                json!(credential_application).to_string(),
+            );
+        }
         if let Some(comment) = self.comment.as_ref() {
             message = message.add_header_field("comment".to_string(), comment.to_string())
         }
         if let Some(goal_code) = self.goal_code.as_ref() {
             message = message.add_header_field("goal_code".to_string(), goal_code.to_string())
         }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
         Ok(message)
     }
 
-    pub fn build_issue_credential(&mut self) -> Result<Message, &'static str> {
+    /// Builds the issue-credential, credentials not valid against the credential schema fail with the reason.
+    pub fn build_issue_credential(&mut self) -> Result<Message, ProtocolError> {
         let mut message =
             Message::new().m_type("https://didcomm.org/issue-credential/2.1/issue-credential");
-        for attachment in &self.attachments {
-            message.append_attachment(
-                AttachmentBuilder::new(true)
-                    .with_id("credential")
-                    .with_media_type("application/json")
-                    .with_data(
-                        AttachmentDataBuilder::new()
-                            .with_link("")
-                            .with_encoded_payload(&encode(
-                                &serde_json::to_string(attachment).unwrap(),
-                            )),
-                    ),
+        let mut attachments = self.attachments.clone();
+        for credential in &self.credentials {
+            credential.validate()?;
+            if let Some(credential_schema) = self.credential_schema.as_ref() {
+                validate_claims(
+                    credential_schema,
+                    &Value::Object(subject_claims(credential)?),
+                )?;
+            }
+            attachments.push(serde_json::to_value(credential).map_err(|_| "invalid credential")?);
+        }
+        for attachment in &attachments {
+            message = attach(
+                message,
+                "credential",
+                self.format.as_deref(),
+                payload(attachment, &self.format),
             );
         }
+        if let Some(credential_response) = self.credential_response.as_ref() {
+            credential_response.validate()?;
+            message = attach(
+                message,
+                "credential-response",
+                Some(formats::CREDENTIAL_RESPONSE),
+                json!(credential_response).to_string(),
+            );
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
 
         Ok(message)
     }
 }
 
+/// Checks a message of the issuance against the schema and the preview of the offer. Requests have to keep the
+/// previewed attributes, issued credentials have to hold exactly them.
+pub fn check_offered(offer: &Message, message: &Message) -> Result<(), ProtocolError> {
+    let preview: CredentialPreview = headers::get(offer, "credential_preview")
+        .and_then(|preview| serde_json::from_str(&preview).ok())
+        .ok_or_else(|| ProtocolError::Other("no credential preview".to_string()))?;
+    let schema: Option<Value> = headers::get(offer, "credential_schema")
+        .map(|schema| serde_json::from_str(&schema))
+        .transpose()
+        .map_err(|_| ProtocolError::Other("invalid credential schema".to_string()))?;
+    if let Some(schema) = schema.as_ref() {
+        preview.validate(schema)?;
+    }
+    if let Some(requested) = headers::get(message, "credential_preview") {
+        let requested: CredentialPreview = serde_json::from_str(&requested)
+            .map_err(|_| ProtocolError::SchemaMismatch("invalid credential preview".to_string()))?;
+        if let Some(schema) = schema.as_ref() {
+            requested.validate(schema)?;
+        }
+        if requested.claims() != preview.claims() {
+            return Err(ProtocolError::SchemaMismatch(
+                "requested attributes differ".to_string(),
+            ));
+        }
+    }
+    let mut checked = headers::get(message, "credential_preview").is_some();
+    if message
+        .get_didcomm_header()
+        .m_type
+        .ends_with("/issue-credential")
+    {
+        for payload in credential_payloads(message) {
+            let credential: VerifiableCredential =
+                serde_json::from_slice(&payload).map_err(|_| {
+                    ProtocolError::SchemaMismatch("credential can not be checked".to_string())
+                })?;
+            if let Some(schema) = schema.as_ref() {
+                validate_claims(schema, &Value::Object(subject_claims(&credential)?))?;
+            }
+            preview.check_credential(&credential)?;
+            checked = true;
+        }
+    }
+    if !checked {
+        return Err(ProtocolError::SchemaMismatch(
+            "no credential to check".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Attaches the payload and adds its format to the `formats` of the message, so every formatted attachment is
+/// listed. Credentials all have the id `credential`, listed once with their format.
+fn attach(mut message: Message, id: &str, format: Option<&str>, payload: String) -> Message {
+    let mut builder = AttachmentBuilder::new(true)
+        .with_id(id)
+        .with_media_type("application/json")
+        .with_data(
+            AttachmentDataBuilder::new()
+                .with_link("")
+                .with_encoded_payload(&encode(payload)),
+        );
+    if let Some(format) = format {
+        builder = builder.with_format(format);
+        let mut formats: Vec<Value> = headers::get(&message, "formats")
+            .and_then(|formats| serde_json::from_str(&formats).ok())
+            .unwrap_or_default();
+        let entry = json!({ "attach_id": id, "format": format });
+        if !formats.contains(&entry) {
+            formats.push(entry);
+        }
+        message = message.add_header_field("formats".to_string(), json!(formats).to_string());
+    }
+    message.append_attachment(builder);
+    message
+}
+
+fn validate_claims(schema: &Value, claims: &Value) -> Result<(), ProtocolError> {
+    let schema = JSONSchema::compile(schema)
+        .map_err(|_| ProtocolError::Other("invalid credential schema".to_string()))?;
+    if let Err(mut errors) = schema.validate(claims) {
+        let reason = errors.next().map(|error| error.to_string());
+        return Err(ProtocolError::SchemaMismatch(reason.unwrap_or_default()));
+    }
+    Ok(())
+}
+
+/// Claims of the single subject of the credential without its id.
+fn subject_claims(credential: &VerifiableCredential) -> Result<Map<String, Value>, ProtocolError> {
+    let mut claims = credential
+        .credential_subject
+        .as_object()
+        .cloned()
+        .ok_or_else(|| ProtocolError::SchemaMismatch("invalid credential subject".to_string()))?;
+    claims.remove("id");
+    Ok(claims)
+}
+
+/// Decoded data of the attached credentials, without the attachments of the Credential Manifest exchange.
+pub(crate) fn credential_payloads(message: &Message) -> Vec<Vec<u8>> {
+    formatted_attachments(message)
+        .into_iter()
+        .filter(|(format, _)| {
+            !matches!(format.as_deref(), Some(format) if formats::MANIFEST_FORMATS.contains(&format))
+        })
+        .map(|(_, payload)| payload)
+        .collect()
+}
+
+/// Decoded data of the base64 and json attachments with their format.
+pub(crate) fn formatted_attachments(message: &Message) -> Vec<(Option<String>, Vec<u8>)> {
+    message
+        .get_attachments()
+        .filter_map(|attachment| serde_json::to_value(attachment).ok())
+        .filter_map(|attachment| {
+            let data = match attachment["data"]["base64"].as_str() {
+                Some(data) => decode(data).ok()?,
+                None => attachment["data"]["json"].as_str()?.as_bytes().to_vec(),
+            };
+            Some((attachment["format"].as_str().map(String::from), data))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::verifiablecredential::DataModelVersion;
     use base64::decode;
     use std::str::from_utf8;
 
@@ -234,4 +665,167 @@ mod tests {
         );
         println!("{}", serde_json::to_string_pretty(&response).unwrap());
     }
+
+    #[test]
+    fn test_build_issue_typed_credential() {
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            json!({ "id": "did:example:alice", "name": "Alice" }),
+        );
+        let response = IssueCredentialResponseBuilder::new()
+            .credential(credential.clone())
+            .build_issue_credential()
+            .unwrap();
+        let response: Value = serde_json::from_str(&response.as_raw_json().unwrap()).unwrap();
+        let attachment: VerifiableCredential = serde_json::from_slice(
+            &decode(
+                response["attachments"][0]["data"]["base64"]
+                    .as_str()
+                    .unwrap(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(attachment, credential);
+
+        credential.credential_subject = json!("Alice");
+        assert_eq!(
+            IssueCredentialResponseBuilder::new()
+                .credential(credential)
+                .build_issue_credential()
+                .unwrap_err(),
+            ProtocolError::Other("invalid credential subject".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_offered_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" }, "age": { "type": "string", "pattern": "^[0-9]+$" } },
+            "required": ["name", "age"],
+            "additionalProperties": false
+        });
+        let preview = CredentialPreview {
+            type_: "https://didcomm.org/issue-credential/2.1/credential-preview".to_string(),
+            attributes: vec![
+                CredentialAttribute::new("name".to_string(), "Alice".to_string()),
+                CredentialAttribute::new("age".to_string(), "42".to_string()),
+            ],
+        };
+        let mut invalid = preview.clone();
+        invalid.attributes[1].value = "old".to_string();
+        let error = IssueCredentialResponseBuilder::new()
+            .credential_preview(invalid)
+            .credential_schema(schema.clone())
+            .build_offer_credential()
+            .unwrap_err();
+        assert_eq!(
+            error,
+            ProtocolError::SchemaMismatch("\"old\" does not match \"^[0-9]+$\"".to_string())
+        );
+
+        let offer = IssueCredentialResponseBuilder::new()
+            .credential_preview(preview.clone())
+            .credential_schema(schema.clone())
+            .build_offer_credential()
+            .unwrap();
+        let mut credential = VerifiableCredential::from_preview(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            Some("did:example:alice"),
+            &preview,
+        );
+        let response = VerifiablePresentation::new(DataModelVersion::V2_0, None, vec![]);
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(credential.clone())
+            .credential(credential.clone())
+            .credential_response(response)
+            .format(formats::LD_PROOF_VC)
+            .credential_schema(schema)
+            .build_issue_credential()
+            .unwrap();
+        assert_eq!(
+            headers::get(&issued, "formats").map(|formats| serde_json::from_str(&formats).unwrap()),
+            Some(json!([
+                { "attach_id": "credential", "format": formats::LD_PROOF_VC },
+                { "attach_id": "credential-response", "format": formats::CREDENTIAL_RESPONSE },
+            ]))
+        );
+        assert_eq!(check_offered(&offer, &issued), Ok(()));
+        let jwt = IssueCredentialResponseBuilder::new()
+            .format(formats::JWT_VC)
+            .attachment(json!("eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl"))
+            .build_issue_credential()
+            .unwrap();
+        assert_eq!(
+            check_offered(&offer, &jwt),
+            Err(ProtocolError::SchemaMismatch(
+                "credential can not be checked".to_string()
+            ))
+        );
+        let empty = IssueCredentialResponseBuilder::new()
+            .build_issue_credential()
+            .unwrap();
+        assert_eq!(
+            check_offered(&offer, &empty),
+            Err(ProtocolError::SchemaMismatch(
+                "no credential to check".to_string()
+            ))
+        );
+
+        credential.credential_subject["name"] = json!("Mallory");
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(credential)
+            .build_issue_credential()
+            .unwrap();
+        let error = check_offered(&offer, &issued).unwrap_err();
+        assert_eq!(
+            error,
+            ProtocolError::SchemaMismatch("attribute name differs".to_string())
+        );
+        let report = error.report(&issued).unwrap();
+        assert_eq!(
+            crate::reportproblem::ProblemCode::from_report(&report)
+                .unwrap()
+                .to_string(),
+            "e.p.msg.schema-mismatch"
+        );
+    }
+
+    #[test]
+    fn test_binary_attribute() {
+        let photo = vec![0xff, 0xd8, 0xff, 0xe0, 0x00];
+        let attribute =
+            CredentialAttribute::binary("photo".to_string(), "image/jpeg", &photo).unwrap();
+        assert!(attribute.is_binary());
+        assert_eq!(attribute.bytes(), Ok(photo.clone()));
+        let json = serde_json::to_value(&attribute).unwrap();
+        assert_eq!(json["mime-type"], "image/jpeg");
+        assert_eq!(json["value"], "_9j_4AA");
+
+        assert_eq!(
+            CredentialAttribute::binary("photo".to_string(), "jpeg", &photo).unwrap_err(),
+            "invalid mime type"
+        );
+        assert_eq!(
+            CredentialAttribute::binary_with_limit("photo".to_string(), "image/jpeg", &photo, 4)
+                .unwrap_err(),
+            "attribute too large"
+        );
+        let mut invalid = attribute;
+        invalid.value = "not base64!".to_string();
+        assert_eq!(
+            IssueCredentialResponseBuilder::new()
+                .credential_preview(CredentialPreview {
+                    type_: "https://didcomm.org/issue-credential/2.1/credential-preview"
+                        .to_string(),
+                    attributes: vec![invalid],
+                })
+                .build_offer_credential()
+                .unwrap_err(),
+            ProtocolError::Other("invalid binary attribute".to_string())
+        );
+    }
 }
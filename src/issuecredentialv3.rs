@@ -0,0 +1,453 @@
+//! # Issue Credential 3.0
+//!
+//! The DIDComm v2 version of issue credential. The fields are in the body of the messages and every attachment tells
+//! its format, so one message can carry a credential in several formats. Version 2.1 is in [crate::issuecredential].
+//! <https://didcomm.org/issue-credential/3.0/>
+
+use crate::connection::Connection;
+use crate::error::ProtocolError;
+use crate::issuecredential::{
+    formats, formatted_attachments, CredentialAttribute, CredentialPreview,
+};
+use crate::jwt::{self, SdJwt};
+use crate::v3message::V3Message;
+use crate::verifiablecredential::{VerifiableCredential, VerifiablePresentation};
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+pub const PROPOSE_CREDENTIAL: &str = "https://didcomm.org/issue-credential/3.0/propose-credential";
+pub const OFFER_CREDENTIAL: &str = "https://didcomm.org/issue-credential/3.0/offer-credential";
+pub const REQUEST_CREDENTIAL: &str = "https://didcomm.org/issue-credential/3.0/request-credential";
+pub const ISSUE_CREDENTIAL: &str = "https://didcomm.org/issue-credential/3.0/issue-credential";
+pub const CREDENTIAL_PREVIEW: &str = "https://didcomm.org/issue-credential/3.0/credential-preview";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialPreviewV3 {
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub body: CredentialPreviewBody,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialPreviewBody {
+    pub attributes: Vec<CredentialAttributeV3>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CredentialAttributeV3 {
+    pub name: String,
+    /// if set, value is base64url encoded binary data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub value: String,
+}
+
+impl From<&CredentialPreview> for CredentialPreviewV3 {
+    fn from(preview: &CredentialPreview) -> Self {
+        CredentialPreviewV3 {
+            type_: CREDENTIAL_PREVIEW.to_string(),
+            id: None,
+            body: CredentialPreviewBody {
+                attributes: preview
+                    .attributes
+                    .iter()
+                    .map(|attribute| CredentialAttributeV3 {
+                        name: attribute.name.to_string(),
+                        media_type: attribute.mime_type.clone(),
+                        value: attribute.value.to_string(),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl From<&CredentialPreviewV3> for CredentialPreview {
+    fn from(preview: &CredentialPreviewV3) -> Self {
+        CredentialPreview {
+            type_: "https://didcomm.org/issue-credential/2.1/credential-preview".to_string(),
+            attributes: preview
+                .body
+                .attributes
+                .iter()
+                .map(|attribute| CredentialAttribute {
+                    name: attribute.name.to_string(),
+                    mime_type: attribute.media_type.clone(),
+                    value: attribute.value.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A received message of the protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueCredentialV3 {
+    pub m_type: String,
+    pub thid: String,
+    pub goal_code: Option<String>,
+    pub comment: Option<String>,
+    pub replacement_id: Option<String>,
+    pub multiple_available: Option<u32>,
+    pub credential_preview: Option<CredentialPreviewV3>,
+    /// format and decoded data of the attachments
+    pub attachments: Vec<(Option<String>, Vec<u8>)>,
+}
+
+impl TryFrom<&Message> for IssueCredentialV3 {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if ![
+            PROPOSE_CREDENTIAL,
+            OFFER_CREDENTIAL,
+            REQUEST_CREDENTIAL,
+            ISSUE_CREDENTIAL,
+        ]
+        .contains(&header.m_type.as_str())
+        {
+            return Err("unsupported message");
+        }
+        let body: Value = match message.get_body() {
+            Ok(body) if !body.is_empty() => {
+                serde_json::from_str(&body).map_err(|_| "invalid body")?
+            }
+            _ => json!({}),
+        };
+        let string = |name: &str| body[name].as_str().map(String::from);
+        Ok(IssueCredentialV3 {
+            m_type: header.m_type.to_string(),
+            thid: header.thid.clone().unwrap_or_else(|| header.id.clone()),
+            goal_code: string("goal_code"),
+            comment: string("comment"),
+            replacement_id: string("replacement_id"),
+            multiple_available: body["multiple_available"].as_u64().map(|n| n as u32),
+            credential_preview: match body.get("credential_preview") {
+                Some(preview) => Some(
+                    serde_json::from_value(preview.clone())
+                        .map_err(|_| "invalid credential preview")?,
+                ),
+                None => None,
+            },
+            attachments: formatted_attachments(message),
+        })
+    }
+}
+
+/// Issue Credential 3.0 Response Builder
+///
+/// # Examples
+///
+/// ```
+/// use serde_json::json;
+/// use didcomm_protocols::issuecredential::formats;
+/// use didcomm_protocols::issuecredentialv3::IssueCredentialV3ResponseBuilder;
+/// use didcomm_protocols::verifiablecredential::{DataModelVersion, VerifiableCredential};
+/// let credential = VerifiableCredential::new(
+///     DataModelVersion::V2_0,
+///     "did:example:issuer",
+///     json!({ "id": "did:example:alice", "degree": "BSc" }),
+/// );
+/// let response = IssueCredentialV3ResponseBuilder::new()
+///     .credential(formats::LD_PROOF_VC, credential)
+///     .build_issue_credential()
+///     .unwrap();
+/// assert_eq!(response.get_didcomm_header().m_type,
+///     "https://didcomm.org/issue-credential/3.0/issue-credential");
+/// ```
+#[derive(Default)]
+pub struct IssueCredentialV3ResponseBuilder {
+    fields: V3Message,
+    credential_preview: Option<CredentialPreviewV3>,
+    replacement_id: Option<String>,
+    multiple_available: Option<u32>,
+}
+
+impl IssueCredentialV3ResponseBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn comment(&mut self, comment: String) -> &mut Self {
+        self.fields.comment = Some(comment);
+        self
+    }
+
+    pub fn goal_code(&mut self, goal_code: String) -> &mut Self {
+        self.fields.goal_code = Some(goal_code);
+        self
+    }
+
+    pub fn credential_preview(&mut self, credential_preview: CredentialPreviewV3) -> &mut Self {
+        self.credential_preview = Some(credential_preview);
+        self
+    }
+
+    /// Id shared by offers and credentials that replace each other, like a new version of a credential.
+    pub fn replacement_id(&mut self, replacement_id: String) -> &mut Self {
+        self.replacement_id = Some(replacement_id);
+        self
+    }
+
+    /// Number of credentials the holder can get from the offer.
+    pub fn multiple_available(&mut self, multiple_available: u32) -> &mut Self {
+        self.multiple_available = Some(multiple_available);
+        self
+    }
+
+    /// Message to respond to.
+    pub fn message(&mut self, message: Message) -> &mut Self {
+        self.fields.message = Some(message);
+        self
+    }
+
+    /// Attaches data in the format, see [crate::issuecredential::formats]. Strings are attached as they are.
+    pub fn attachment(&mut self, format: &str, attachment: Value) -> &mut Self {
+        self.fields
+            .attachments
+            .push((format.to_string(), attachment));
+        self
+    }
+
+    /// Attaches the credential, it is validated when the message is built.
+    pub fn credential(&mut self, format: &str, credential: VerifiableCredential) -> &mut Self {
+        self.attachment(format, json!(credential))
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.fields.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.fields.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.fields.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.fields.please_ack = please_ack;
+        self
+    }
+
+    /// Builds the next message of the protocol for the received message.
+    pub fn build(&mut self) -> Result<Message, ProtocolError> {
+        match &self.fields.message {
+            Some(message) => match message.get_didcomm_header().m_type.as_str() {
+                PROPOSE_CREDENTIAL => self.build_offer_credential(),
+                OFFER_CREDENTIAL => self.build_request_credential(),
+                REQUEST_CREDENTIAL => self.build_issue_credential(),
+                m_type => Err(ProtocolError::UnsupportedMessage(m_type.to_string())),
+            },
+            None => Err("no message".into()),
+        }
+    }
+
+    pub fn build_propose_credential(&mut self) -> Result<Message, ProtocolError> {
+        let body = self.body(&["credential_preview"]);
+        Ok(self.fields.build(PROPOSE_CREDENTIAL, body)?)
+    }
+
+    pub fn build_offer_credential(&mut self) -> Result<Message, ProtocolError> {
+        if self.credential_preview.is_none() && self.fields.attachments.is_empty() {
+            return Err("no credential preview".into());
+        }
+        let body = self.body(&["credential_preview", "replacement_id", "multiple_available"]);
+        Ok(self.fields.build(OFFER_CREDENTIAL, body)?)
+    }
+
+    pub fn build_request_credential(&mut self) -> Result<Message, ProtocolError> {
+        let body = self.body(&[]);
+        Ok(self.fields.build(REQUEST_CREDENTIAL, body)?)
+    }
+
+    /// Builds the issue-credential, every attachment has to be a valid credential in its format.
+    pub fn build_issue_credential(&mut self) -> Result<Message, ProtocolError> {
+        if self.fields.attachments.is_empty() {
+            return Err("no credential".into());
+        }
+        for (format, attachment) in &self.fields.attachments {
+            check_attachment(format, attachment)?;
+        }
+        let body = self.body(&["replacement_id"]);
+        Ok(self.fields.build(ISSUE_CREDENTIAL, body)?)
+    }
+
+    /// The body with `goal_code`, `comment` and the named fields that are set.
+    fn body(&self, fields: &[&str]) -> Map<String, Value> {
+        let mut body = self.fields.body();
+        for field in fields {
+            let value = match *field {
+                "credential_preview" => self
+                    .credential_preview
+                    .as_ref()
+                    .map(|preview| json!(preview)),
+                "replacement_id" => self.replacement_id.as_ref().map(|id| json!(id)),
+                "multiple_available" => self.multiple_available.map(|n| json!(n)),
+                _ => None,
+            };
+            if let Some(value) = value {
+                body.insert(field.to_string(), value);
+            }
+        }
+        body
+    }
+}
+
+/// Parses the attached credential in its format and validates it, without checking its proof. Credential Manifest
+/// fulfillments and responses are presentations of the issued credentials.
+fn check_attachment(format: &str, attachment: &Value) -> Result<(), &'static str> {
+    match format {
+        formats::LD_PROOF_VC => serde_json::from_value::<VerifiableCredential>(attachment.clone())
+            .map_err(|_| "invalid credential")?
+            .validate(),
+        formats::JWT_VC => {
+            let (_, claims) = jwt::decode(attachment.as_str().ok_or("invalid credential")?)?;
+            serde_json::from_value::<VerifiableCredential>(
+                claims.get("vc").cloned().ok_or("no credential")?,
+            )
+            .map_err(|_| "invalid credential")?
+            .validate()
+        }
+        formats::SD_JWT_VC => {
+            let sd_jwt: SdJwt = attachment.as_str().ok_or("invalid credential")?.parse()?;
+            jwt::decode(&sd_jwt.jwt).map(|_| ())
+        }
+        formats::CREDENTIAL_FULFILLMENT | formats::CREDENTIAL_RESPONSE => {
+            serde_json::from_value::<VerifiablePresentation>(attachment.clone())
+                .map_err(|_| "invalid presentation")?
+                .validate()
+        }
+        _ => Err("unsupported credential format"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jwt::JwtIssuer;
+    use crate::verifiablecredential::DataModelVersion;
+    use crate::IssueCredentialResponseBuilder;
+    use did_key::{generate, Ed25519KeyPair};
+
+    fn preview() -> CredentialPreview {
+        CredentialPreview {
+            type_: "https://didcomm.org/issue-credential/2.1/credential-preview".to_string(),
+            attributes: vec![CredentialAttribute::new(
+                "name".to_string(),
+                "Alice".to_string(),
+            )],
+        }
+    }
+
+    #[test]
+    fn test_issue_credential_v3() {
+        let offer = IssueCredentialV3ResponseBuilder::new()
+            .credential_preview((&preview()).into())
+            .replacement_id("passport".to_string())
+            .multiple_available(2)
+            .comment("Your passport".to_string())
+            .build_offer_credential()
+            .unwrap();
+        let body: Value = serde_json::from_str(&offer.get_body().unwrap()).unwrap();
+        assert_eq!(
+            body["credential_preview"]["body"]["attributes"][0]["value"],
+            "Alice"
+        );
+        let received = IssueCredentialV3::try_from(&offer).unwrap();
+        assert_eq!(received.multiple_available, Some(2));
+        assert_eq!(received.replacement_id, Some("passport".to_string()));
+        assert_eq!(
+            CredentialPreview::from(received.credential_preview.as_ref().unwrap()),
+            preview()
+        );
+
+        let request = IssueCredentialV3ResponseBuilder::new()
+            .message(offer.clone())
+            .build()
+            .unwrap();
+        assert_eq!(request.get_didcomm_header().m_type, REQUEST_CREDENTIAL);
+        let credential = VerifiableCredential::from_preview(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            Some("did:example:alice"),
+            &preview(),
+        );
+        let jwt_issuer = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer")));
+        let jwt = jwt_issuer
+            .sign_credential(&VerifiableCredential::new(
+                DataModelVersion::V2_0,
+                &jwt_issuer.did(),
+                json!({ "id": "did:example:alice", "name": "Alice" }),
+            ))
+            .unwrap();
+        assert_eq!(
+            IssueCredentialV3ResponseBuilder::new()
+                .message(request.clone())
+                .build(),
+            Err(ProtocolError::Other("no credential".to_string()))
+        );
+        assert_eq!(
+            IssueCredentialV3ResponseBuilder::new()
+                .attachment(formats::JWT_VC, json!("eyJhbGciOiJFZERTQSJ9.e30.c2ln"))
+                .build_issue_credential(),
+            Err(ProtocolError::Other("no credential".to_string()))
+        );
+        assert_eq!(
+            IssueCredentialV3ResponseBuilder::new()
+                .attachment(formats::LD_PROOF_VC, json!({ "name": "Alice" }))
+                .build_issue_credential(),
+            Err(ProtocolError::Other("invalid credential".to_string()))
+        );
+        let issued = IssueCredentialV3ResponseBuilder::new()
+            .message(request)
+            .credential(formats::LD_PROOF_VC, credential.clone())
+            .attachment(formats::JWT_VC, json!(jwt))
+            .build()
+            .unwrap();
+        assert_eq!(
+            issued.get_didcomm_header().thid.as_ref(),
+            Some(&offer.get_didcomm_header().id)
+        );
+        let received = IssueCredentialV3::try_from(&issued).unwrap();
+        assert_eq!(received.m_type, ISSUE_CREDENTIAL);
+        assert_eq!(
+            received.attachments[0].0,
+            Some(formats::LD_PROOF_VC.to_string())
+        );
+        assert_eq!(
+            serde_json::from_slice::<VerifiableCredential>(&received.attachments[0].1).unwrap(),
+            credential
+        );
+        assert_eq!(received.attachments[1].1, jwt.as_bytes());
+    }
+
+    #[test]
+    fn test_v2_messages_unsupported() {
+        let offer = IssueCredentialResponseBuilder::new()
+            .credential_preview(preview())
+            .build_offer_credential()
+            .unwrap();
+        assert_eq!(
+            IssueCredentialV3::try_from(&offer).unwrap_err(),
+            "unsupported message"
+        );
+        assert_eq!(
+            IssueCredentialV3ResponseBuilder::new()
+                .message(offer)
+                .build()
+                .unwrap_err(),
+            ProtocolError::UnsupportedMessage(
+                "https://didcomm.org/issue-credential/2.1/offer-credential".to_string()
+            )
+        );
+    }
+}
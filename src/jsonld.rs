@@ -0,0 +1,267 @@
+//! # JSON-LD
+//!
+//! Turns JSON-LD credentials and presentations into RDF datasets and canonicalizes them with RDFC-1.0, the way data
+//! integrity proofs of the `eddsa-rdfc-2022` cryptosuite sign them. Contexts are bundled, nothing is fetched.
+//!
+//! The expansion runs in safe mode: properties, types and ids that do not expand to absolute IRIs fail instead of
+//! being dropped, so no claim of a signed document is left out of its signature.
+//! <https://www.w3.org/TR/json-ld11-api/>
+//! <https://www.w3.org/TR/rdf-canon/>
+
+use json_ld::expansion::{Action, Policy};
+use json_ld::loader::map::EntryNotFound;
+use json_ld::rdf_types::generator::Blank;
+use json_ld::rdf_types::{Id, LiteralType, Object};
+use json_ld::syntax::ErrorCode;
+use json_ld::{Iri, IriBuf, JsonLdProcessor, LoadError, Loader, Options, RemoteDocument};
+use oxrdf::{BlankNode, GraphName, Literal, NamedNode, NamedOrBlankNode, Quad, Term};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Context documents known without fetching them.
+pub const BUNDLED_CONTEXTS: &[(&str, &str)] = &[
+    (
+        "https://www.w3.org/2018/credentials/v1",
+        include_str!("contexts/credentials-v1.jsonld"),
+    ),
+    (
+        "https://www.w3.org/ns/credentials/v2",
+        include_str!("contexts/credentials-v2.jsonld"),
+    ),
+    (
+        "https://www.w3.org/ns/credentials/examples/v2",
+        include_str!("contexts/credentials-examples-v2.jsonld"),
+    ),
+    (
+        "https://w3id.org/security/data-integrity/v2",
+        include_str!("contexts/data-integrity-v2.jsonld"),
+    ),
+    (
+        "https://identity.foundation/presentation-exchange/submission/v1",
+        include_str!("contexts/presentation-exchange-submission-v1.jsonld"),
+    ),
+    (
+        "https://identity.foundation/credential-manifest/application/v1",
+        include_str!("contexts/credential-manifest-application-v1.jsonld"),
+    ),
+    (
+        "https://identity.foundation/credential-manifest/response/v1",
+        include_str!("contexts/credential-manifest-response-v1.jsonld"),
+    ),
+    (
+        "https://identity.foundation/credential-manifest/fulfillment/v1",
+        include_str!("contexts/credential-manifest-fulfillment-v1.jsonld"),
+    ),
+];
+
+/// Context documents by URL.
+#[derive(Debug, Clone)]
+pub struct DocumentLoader {
+    documents: HashMap<String, Value>,
+}
+
+impl Default for DocumentLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentLoader {
+    /// A loader knowing the bundled contexts.
+    pub fn new() -> Self {
+        DocumentLoader {
+            documents: BUNDLED_CONTEXTS
+                .iter()
+                .map(|(url, document)| {
+                    (
+                        url.to_string(),
+                        serde_json::from_str(document).expect("invalid bundled context"),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds a context document like `{ "@context": { ... } }`, replacing a known one.
+    pub fn add(&mut self, url: &str, document: Value) -> &mut Self {
+        self.documents.insert(url.to_string(), document);
+        self
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Value> {
+        self.documents.get(url)
+    }
+}
+
+impl Loader for DocumentLoader {
+    async fn load(&self, url: &Iri) -> Result<RemoteDocument<IriBuf>, LoadError> {
+        match self.get(url.as_str()) {
+            Some(document) => Ok(RemoteDocument::new(
+                Some(url.to_owned()),
+                None,
+                json_syntax::Value::from_serde_json(document.clone()),
+            )),
+            None => Err(LoadError::new(url.to_owned(), EntryNotFound)),
+        }
+    }
+}
+
+/// Canonical N-Quads of the document.
+pub fn canonicalize(document: &Value, loader: &DocumentLoader) -> Result<String, &'static str> {
+    let document = RemoteDocument::new(
+        None,
+        None,
+        json_syntax::Value::from_serde_json(document.clone()),
+    );
+    let options = Options {
+        expansion_policy: Policy {
+            invalid: Action::Reject,
+            vocab: Action::Keep,
+            allow_undefined: false,
+        },
+        ..Options::default()
+    };
+    let mut generator = Blank::new();
+    let mut rdf =
+        futures::executor::block_on(document.to_rdf_using(&mut generator, loader, options))
+            .map_err(|error| match error.code() {
+                ErrorCode::LoadingDocumentFailed | ErrorCode::LoadingRemoteContextFailed => {
+                    "unknown context"
+                }
+                ErrorCode::KeyExpansionFailed | ErrorCode::InvalidIriMapping => "undefined term",
+                _ => "invalid document",
+            })?;
+    let quads: Vec<Quad> = rdf
+        .cloned_quads()
+        .map(|quad| {
+            Quad::new(
+                subject(quad.0),
+                NamedNode::new_unchecked(quad.1.as_str()),
+                match quad.2 {
+                    Object::Id(id) => Term::from(subject(id)),
+                    Object::Literal(literal) => Term::from(match literal.type_ {
+                        LiteralType::Any(datatype) => Literal::new_typed_literal(
+                            literal.value,
+                            NamedNode::new_unchecked(datatype.as_str()),
+                        ),
+                        LiteralType::LangString(language) => {
+                            Literal::new_language_tagged_literal_unchecked(
+                                literal.value,
+                                language.as_str().to_ascii_lowercase(),
+                            )
+                        }
+                    }),
+                },
+                match quad.3 {
+                    Some(graph) => GraphName::from(subject(graph)),
+                    None => GraphName::DefaultGraph,
+                },
+            )
+        })
+        .collect();
+    rdf_canon::canonicalize_quads(&quads).map_err(|_| "invalid document")
+}
+
+fn subject(id: Id) -> NamedOrBlankNode {
+    match id {
+        Id::Iri(iri) => NamedNode::new_unchecked(iri.as_str()).into(),
+        Id::Blank(blank) => BlankNode::new_unchecked(blank.suffix()).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_canonicalize_credential() {
+        // the credential of the eddsa-rdfc-2022 example of the Data Integrity EdDSA Cryptosuites specification
+        let credential = json!({
+            "@context": [
+                "https://www.w3.org/ns/credentials/v2",
+                "https://www.w3.org/ns/credentials/examples/v2"
+            ],
+            "id": "urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33",
+            "type": ["VerifiableCredential", "AlumniCredential"],
+            "name": "Alumni Credential",
+            "description": "A minimum viable example of an Alumni Credential.",
+            "issuer": "https://vc.example/issuers/5678",
+            "validFrom": "2023-01-01T00:00:00Z",
+            "credentialSubject": {
+                "id": "did:example:abcdefgh",
+                "alumniOf": "The School of Examples"
+            }
+        });
+        let canonical = canonicalize(&credential, &DocumentLoader::new()).unwrap();
+        assert_eq!(
+            canonical,
+            concat!(
+                "<did:example:abcdefgh> <https://www.w3.org/ns/credentials/examples#alumniOf> \"The School of Examples\" .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://www.w3.org/2018/credentials#VerifiableCredential> .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://www.w3.org/ns/credentials/examples#AlumniCredential> .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <https://schema.org/description> \"A minimum viable example of an Alumni Credential.\" .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <https://schema.org/name> \"Alumni Credential\" .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <https://www.w3.org/2018/credentials#credentialSubject> <did:example:abcdefgh> .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <https://www.w3.org/2018/credentials#issuer> <https://vc.example/issuers/5678> .\n",
+                "<urn:uuid:58172aac-d8ba-11ed-83dd-0b3aef56cc33> <https://www.w3.org/2018/credentials#validFrom> \"2023-01-01T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n",
+            )
+        );
+
+        let proof = json!({
+            "@context": credential["@context"],
+            "type": "DataIntegrityProof",
+            "cryptosuite": "eddsa-rdfc-2022",
+            "created": "2023-02-24T23:36:38Z",
+            "verificationMethod": "did:key:z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2#z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2",
+            "proofPurpose": "assertionMethod"
+        });
+        assert_eq!(
+            canonicalize(&proof, &DocumentLoader::new()).unwrap(),
+            concat!(
+                "_:c14n0 <http://purl.org/dc/terms/created> \"2023-02-24T23:36:38Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n",
+                "_:c14n0 <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://w3id.org/security#DataIntegrityProof> .\n",
+                "_:c14n0 <https://w3id.org/security#cryptosuite> \"eddsa-rdfc-2022\"^^<https://w3id.org/security#cryptosuiteString> .\n",
+                "_:c14n0 <https://w3id.org/security#proofPurpose> <https://w3id.org/security#assertionMethod> .\n",
+                "_:c14n0 <https://w3id.org/security#verificationMethod> <did:key:z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2#z6MkrJVnaZkeFzdQyMZu1cgjg7k1pZZ6pvBQ7XJPt4swbTQ2> .\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_blank_nodes() {
+        let loader = DocumentLoader::new();
+        let document = |first: &str, second: &str| {
+            json!({
+                "@context": { "@vocab": "https://example.com/#" },
+                "@graph": [
+                    { "@id": first, "knows": { "@id": second }, "name": "A" },
+                    { "@id": second, "knows": { "@id": first }, "name": "B" },
+                    { "@id": "_:c", "knows": [{ "@id": first }, { "@id": second }] }
+                ]
+            })
+        };
+        let canonical = canonicalize(&document("_:x", "_:y"), &loader).unwrap();
+        assert_eq!(
+            canonical,
+            canonicalize(&document("_:y", "_:x"), &loader).unwrap()
+        );
+        assert!(canonical.contains("_:c14n2 <https://example.com/#knows> _:c14n0 .\n"));
+        assert!(!canonical.contains("_:b"));
+
+        assert_eq!(
+            canonicalize(
+                &json!({ "@context": "https://example.com/context" }),
+                &loader
+            ),
+            Err("unknown context")
+        );
+        assert_eq!(
+            canonicalize(
+                &json!({ "@context": "https://www.w3.org/2018/credentials/v1", "name": "Alice" }),
+                &loader
+            ),
+            Err("undefined term")
+        );
+    }
+}
@@ -0,0 +1,716 @@
+//! # JWT
+//!
+//! JWT encoded credentials and presentations signed with did:key Ed25519 (`EdDSA`) or P-256 (`ES256`) keys, and SD-JWT
+//! credentials with selectively disclosable claims and key binding.
+//! <https://www.w3.org/TR/vc-data-model/#json-web-token>
+//! <https://datatracker.ietf.org/doc/draft-ietf-oauth-selective-disclosure-jwt/>
+//! <https://datatracker.ietf.org/doc/draft-ietf-oauth-sd-jwt-vc/>
+//!
+//! Only top level claims can be selectively disclosed.
+
+use crate::verifiablecredential::{DataModelVersion, VerifiableCredential, CONTEXT_V1};
+use base58::FromBase58;
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use chrono::{DateTime, SecondsFormat, Utc};
+use did_key::{CoreSign, Ed25519KeyPair, Fingerprint, KeyMaterial, P256KeyPair, PatchedKeyPair};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Signs JWTs with a did:key key pair.
+pub struct JwtIssuer {
+    key: PatchedKeyPair,
+}
+
+impl JwtIssuer {
+    pub fn new(key: PatchedKeyPair) -> Self {
+        JwtIssuer { key }
+    }
+
+    /// The did:key of the issuer.
+    pub fn did(&self) -> String {
+        format!("did:key:{}", self.key.fingerprint())
+    }
+
+    fn kid(&self) -> String {
+        format!("{}#{}", self.did(), self.key.fingerprint())
+    }
+
+    /// The public key as JWK, to bind SD-JWT credentials to with the `cnf` claim.
+    pub fn jwk(&self) -> Result<Value, &'static str> {
+        let public_key = self.key.public_key_bytes();
+        match algorithm(&self.key)? {
+            "EdDSA" => Ok(json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": encode_config(public_key, URL_SAFE_NO_PAD),
+            })),
+            // uncompressed SEC1 point
+            _ if public_key.len() == 65 && public_key[0] == 0x04 => Ok(json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": encode_config(&public_key[1..33], URL_SAFE_NO_PAD),
+                "y": encode_config(&public_key[33..], URL_SAFE_NO_PAD),
+            })),
+            _ => Err("unsupported key"),
+        }
+    }
+
+    pub fn sign(&self, claims: &Value, typ: &str) -> Result<String, &'static str> {
+        let header = json!({ "alg": algorithm(&self.key)?, "typ": typ, "kid": self.kid() });
+        let input = format!("{}.{}", encode_json(&header), encode_json(claims));
+        let signature = self.key.sign(input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            input,
+            encode_config(signature, URL_SAFE_NO_PAD)
+        ))
+    }
+
+    /// Encodes the credential as JWT-VC, the issuer of the credential has to be the did of the key.
+    pub fn sign_credential(
+        &self,
+        credential: &VerifiableCredential,
+    ) -> Result<String, &'static str> {
+        credential.validate()?;
+        if credential.issuer.id() != self.did() {
+            return Err("invalid issuer");
+        }
+        let mut claims = json!({
+            "iss": self.did(),
+            "vc": credential,
+        });
+        if let Some(id) = credential.id.as_ref() {
+            claims["jti"] = json!(id);
+        }
+        if let Some(subject) = credential.credential_subject["id"].as_str() {
+            claims["sub"] = json!(subject);
+        }
+        if let Some(from) = credential
+            .issuance_date
+            .as_ref()
+            .or(credential.valid_from.as_ref())
+        {
+            claims["nbf"] = json!(timestamp(from)?);
+        }
+        if let Some(until) = credential
+            .expiration_date
+            .as_ref()
+            .or(credential.valid_until.as_ref())
+        {
+            claims["exp"] = json!(timestamp(until)?);
+        }
+        self.sign(&claims, "JWT")
+    }
+
+    /// Encodes the JWT-VC credentials into a JWT-VP for the audience, bound to the nonce of the verifier.
+    pub fn sign_presentation(
+        &self,
+        credentials: &[String],
+        audience: Option<&str>,
+        nonce: Option<&str>,
+    ) -> Result<String, &'static str> {
+        let mut claims = json!({
+            "iss": self.did(),
+            "vp": {
+                "@context": [CONTEXT_V1],
+                "type": ["VerifiablePresentation"],
+                "verifiableCredential": credentials,
+            },
+        });
+        if let Some(audience) = audience {
+            claims["aud"] = json!(audience);
+        }
+        if let Some(nonce) = nonce {
+            claims["nonce"] = json!(nonce);
+        }
+        self.sign(&claims, "JWT")
+    }
+}
+
+/// Verifies the signature with the key of the `kid` header and the validity period, returns the claims.
+pub fn verify(jwt: &str) -> Result<Map<String, Value>, &'static str> {
+    verify_at(jwt, Utc::now())
+}
+
+pub fn verify_at(jwt: &str, now: DateTime<Utc>) -> Result<Map<String, Value>, &'static str> {
+    let (header, claims) = decode(jwt)?;
+    let kid = header["kid"].as_str().ok_or("no kid")?;
+    let key = did_key::resolve(did_of(kid)).map_err(|_| "invalid kid")?;
+    check_signature(jwt, &header, &key)?;
+    if claims.get("iss").and_then(Value::as_str) != Some(did_of(kid)) {
+        return Err("invalid issuer");
+    }
+    check_validity(&claims, now)?;
+    Ok(claims)
+}
+
+/// Header and claims of the JWT, without checking the signature.
+pub(crate) fn decode(jwt: &str) -> Result<(Value, Map<String, Value>), &'static str> {
+    let mut parts = jwt.split('.');
+    let header = decode_json(parts.next().unwrap_or_default())?;
+    let claims = match decode_json(parts.next().ok_or("invalid jwt")?)? {
+        Value::Object(claims) => claims,
+        _ => return Err("invalid jwt"),
+    };
+    Ok((header, claims))
+}
+
+fn check_signature(jwt: &str, header: &Value, key: &PatchedKeyPair) -> Result<(), &'static str> {
+    if header["alg"].as_str() != Some(algorithm(key)?) {
+        return Err("invalid algorithm");
+    }
+    let (input, signature) = jwt.rsplit_once('.').ok_or("invalid jwt")?;
+    let signature = decode_config(signature, URL_SAFE_NO_PAD).map_err(|_| "invalid jwt")?;
+    key.verify(input.as_bytes(), &signature)
+        .map_err(|_| "invalid signature")
+}
+
+fn check_validity(claims: &Map<String, Value>, now: DateTime<Utc>) -> Result<(), &'static str> {
+    let now = now.timestamp();
+    if let Some(nbf) = claims.get("nbf").and_then(Value::as_i64) {
+        if nbf > now {
+            return Err("jwt not yet valid");
+        }
+    }
+    if let Some(exp) = claims.get("exp").and_then(Value::as_i64) {
+        if exp < now {
+            return Err("jwt expired");
+        }
+    }
+    Ok(())
+}
+
+/// Verifies a JWT-VC and returns the credential.
+pub fn verify_credential(jwt: &str) -> Result<VerifiableCredential, &'static str> {
+    credential_of(verify(jwt)?)
+}
+
+fn credential_of(claims: Map<String, Value>) -> Result<VerifiableCredential, &'static str> {
+    let credential: VerifiableCredential =
+        serde_json::from_value(claims.get("vc").cloned().ok_or("no credential")?)
+            .map_err(|_| "invalid credential")?;
+    credential.validate()?;
+    if Some(credential.issuer.id()) != claims.get("iss").and_then(Value::as_str) {
+        return Err("invalid issuer");
+    }
+    Ok(credential)
+}
+
+/// Verifies a JWT-VP for the audience and nonce of the verifier and the credentials in it, returns the credentials.
+/// Every credential has to be issued to the holder signing the presentation.
+pub fn verify_presentation(
+    jwt: &str,
+    audience: Option<&str>,
+    nonce: Option<&str>,
+) -> Result<Vec<VerifiableCredential>, &'static str> {
+    let claims = verify(jwt)?;
+    if let Some(audience) = audience {
+        if !has_audience(&claims, audience) {
+            return Err("invalid audience");
+        }
+    }
+    if nonce.is_some() && claims.get("nonce").and_then(Value::as_str) != nonce {
+        return Err("invalid nonce");
+    }
+    let holder = claims.get("iss").and_then(Value::as_str);
+    claims
+        .get("vp")
+        .and_then(|vp| vp["verifiableCredential"].as_array())
+        .ok_or("no presentation")?
+        .iter()
+        .map(|credential| {
+            let claims = verify(credential.as_str().ok_or("invalid credential")?)?;
+            if claims.get("sub").and_then(Value::as_str) != holder {
+                return Err("invalid holder");
+            }
+            credential_of(claims)
+        })
+        .collect()
+}
+
+/// The `aud` claim is a string or an array of strings.
+fn has_audience(claims: &Map<String, Value>, audience: &str) -> bool {
+    match claims.get("aud") {
+        Some(Value::String(aud)) => aud == audience,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud == audience),
+        _ => false,
+    }
+}
+
+/// A claim of an SD-JWT, released by the holder by including the disclosure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disclosure {
+    pub salt: String,
+    pub name: String,
+    pub value: Value,
+    /// base64url encoded JSON array of salt, name and value
+    pub encoded: String,
+}
+
+impl Disclosure {
+    pub fn new(name: &str, value: Value) -> Self {
+        let salt = Uuid::new_v4().to_string();
+        let encoded = encode_json(&json!([salt, name, value]));
+        Disclosure {
+            salt,
+            name: name.to_string(),
+            value,
+            encoded,
+        }
+    }
+
+    /// Hash of the disclosure listed in the `_sd` claim.
+    pub fn digest(&self) -> String {
+        digest(&self.encoded)
+    }
+}
+
+impl FromStr for Disclosure {
+    type Err = &'static str;
+
+    fn from_str(encoded: &str) -> Result<Self, Self::Err> {
+        match decode_json(encoded)? {
+            Value::Array(values) if values.len() == 3 => Ok(Disclosure {
+                salt: values[0].as_str().ok_or("invalid disclosure")?.to_string(),
+                name: values[1].as_str().ok_or("invalid disclosure")?.to_string(),
+                value: values[2].clone(),
+                encoded: encoded.to_string(),
+            }),
+            _ => Err("invalid disclosure"),
+        }
+    }
+}
+
+/// SD-JWT VC, serialized as the issuer signed JWT, the disclosures and an optional key binding JWT separated by `~`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdJwt {
+    pub jwt: String,
+    pub disclosures: Vec<Disclosure>,
+    pub key_binding: Option<String>,
+}
+
+/// Claims set by the issuer of an SD-JWT.
+const RESERVED_CLAIMS: &[&str] = &["iss", "iat", "vct", "cnf", "_sd", "_sd_alg", "..."];
+
+impl SdJwt {
+    /// Issues the claims of type `vct` to the holder with the public key `holder` as JWK, the listed claims are
+    /// selectively disclosable.
+    pub fn issue(
+        issuer: &JwtIssuer,
+        vct: &str,
+        claims: Map<String, Value>,
+        disclosable: &[&str],
+        holder: &Value,
+    ) -> Result<Self, &'static str> {
+        let mut payload = Map::new();
+        let mut disclosures = Vec::new();
+        for (name, value) in claims {
+            if RESERVED_CLAIMS.contains(&name.as_str()) {
+                return Err("reserved claim");
+            }
+            if disclosable.contains(&name.as_str()) {
+                disclosures.push(Disclosure::new(&name, value));
+            } else {
+                payload.insert(name, value);
+            }
+        }
+        let mut digests: Vec<String> = disclosures.iter().map(Disclosure::digest).collect();
+        digests.sort();
+        payload.insert("iss".to_string(), json!(issuer.did()));
+        payload.insert("iat".to_string(), json!(Utc::now().timestamp()));
+        payload.insert("vct".to_string(), json!(vct));
+        payload.insert("cnf".to_string(), json!({ "jwk": holder }));
+        payload.insert("_sd".to_string(), json!(digests));
+        payload.insert("_sd_alg".to_string(), json!("sha-256"));
+        Ok(SdJwt {
+            jwt: issuer.sign(&Value::Object(payload), "vc+sd-jwt")?,
+            disclosures,
+            key_binding: None,
+        })
+    }
+
+    /// Keeps only the disclosures of the named claims, to be presented by the holder.
+    pub fn disclose(&self, names: &[&str]) -> Self {
+        SdJwt {
+            jwt: self.jwt.to_string(),
+            disclosures: self
+                .disclosures
+                .iter()
+                .filter(|disclosure| names.contains(&disclosure.name.as_str()))
+                .cloned()
+                .collect(),
+            key_binding: None,
+        }
+    }
+
+    /// Adds a key binding JWT signed by the holder for the audience and the nonce of the verifier.
+    pub fn bind(
+        &self,
+        holder: &JwtIssuer,
+        audience: &str,
+        nonce: &str,
+    ) -> Result<Self, &'static str> {
+        let mut presented = self.clone();
+        presented.key_binding = None;
+        let claims = json!({
+            "iat": Utc::now().timestamp(),
+            "aud": audience,
+            "nonce": nonce,
+            "sd_hash": digest(&presented.to_string()),
+        });
+        presented.key_binding = Some(holder.sign(&claims, "kb+jwt")?);
+        Ok(presented)
+    }
+
+    /// Verifies the issuer signature, the disclosures and the key binding, returns the disclosed claims. A credential
+    /// bound to a holder key with `cnf` needs a key binding JWT signed by that key for the audience and the nonce of
+    /// the verifier.
+    pub fn verify(&self, audience: &str, nonce: &str) -> Result<Map<String, Value>, &'static str> {
+        let claims = self.verify_issued()?;
+        let key_binding = match (claims.get("cnf"), self.key_binding.as_ref()) {
+            (None, None) => None,
+            (Some(_), Some(key_binding)) => Some(key_binding),
+            (Some(_), None) => return Err("no key binding"),
+            (None, Some(_)) => return Err("invalid key binding"),
+        };
+        if let Some(key_binding) = key_binding {
+            let (header, binding) = decode(key_binding)?;
+            if header["typ"] != "kb+jwt" {
+                return Err("invalid key binding");
+            }
+            check_signature(key_binding, &header, &key_of(&claims["cnf"]["jwk"])?)?;
+            if !binding.get("iat").is_some_and(Value::is_i64) {
+                return Err("invalid key binding");
+            }
+            check_validity(&binding, Utc::now())?;
+            if !has_audience(&binding, audience) {
+                return Err("invalid audience");
+            }
+            if binding.get("nonce").and_then(Value::as_str) != Some(nonce) {
+                return Err("invalid nonce");
+            }
+            let mut presented = self.clone();
+            presented.key_binding = None;
+            if binding.get("sd_hash").and_then(Value::as_str)
+                != Some(&digest(&presented.to_string()))
+            {
+                return Err("invalid key binding");
+            }
+        }
+        Ok(claims)
+    }
+
+    /// Verifies the issuer signature and the disclosures of an SD-JWT as issued to the holder, without a key binding,
+    /// and returns the claims as a credential of type `vct` about the subject.
+    pub fn verify_credential(&self) -> Result<VerifiableCredential, &'static str> {
+        if self.key_binding.is_some() {
+            return Err("invalid key binding");
+        }
+        let mut claims = self.verify_issued()?;
+        let issuer = claims.remove("iss");
+        let vct = claims.remove("vct");
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            issuer
+                .as_ref()
+                .and_then(Value::as_str)
+                .ok_or("invalid issuer")?,
+            json!({}),
+        );
+        credential.type_.push(
+            vct.as_ref()
+                .and_then(Value::as_str)
+                .ok_or("invalid credential")?
+                .to_string(),
+        );
+        credential.valid_from = claims.remove("iat").map(|iat| date(&iat)).transpose()?;
+        credential.valid_until = claims.remove("exp").map(|exp| date(&exp)).transpose()?;
+        for name in ["nbf", "cnf", "status"] {
+            claims.remove(name);
+        }
+        if let Some(subject) = claims.remove("sub") {
+            claims.insert("id".to_string(), subject);
+        }
+        credential.credential_subject = Value::Object(claims);
+        credential.validate()?;
+        Ok(credential)
+    }
+
+    /// Verifies the issuer signature and the disclosures, returns the disclosed claims.
+    fn verify_issued(&self) -> Result<Map<String, Value>, &'static str> {
+        let mut claims = verify(&self.jwt)?;
+        let digests: Vec<String> = match claims.remove("_sd") {
+            Some(Value::Array(digests)) => digests
+                .iter()
+                .filter_map(|digest| digest.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if claims.remove("_sd_alg").unwrap_or(json!("sha-256")) != "sha-256" {
+            return Err("unsupported digest");
+        }
+        for disclosure in &self.disclosures {
+            if !digests.contains(&disclosure.digest()) {
+                return Err("invalid disclosure");
+            }
+            if claims
+                .insert(disclosure.name.to_string(), disclosure.value.clone())
+                .is_some()
+            {
+                return Err("duplicate claim");
+            }
+        }
+        Ok(claims)
+    }
+}
+
+impl fmt::Display for SdJwt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}~", self.jwt)?;
+        for disclosure in &self.disclosures {
+            write!(f, "{}~", disclosure.encoded)?;
+        }
+        write!(f, "{}", self.key_binding.as_deref().unwrap_or_default())
+    }
+}
+
+impl FromStr for SdJwt {
+    type Err = &'static str;
+
+    fn from_str(sd_jwt: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = sd_jwt.split('~').collect();
+        if parts.len() < 2 {
+            return Err("invalid sd-jwt");
+        }
+        let key_binding = parts.pop().filter(|jwt| !jwt.is_empty());
+        Ok(SdJwt {
+            jwt: parts[0].to_string(),
+            disclosures: parts[1..]
+                .iter()
+                .map(|disclosure| disclosure.parse())
+                .collect::<Result<_, _>>()?,
+            key_binding: key_binding.map(String::from),
+        })
+    }
+}
+
+/// Algorithm of the key by its multicodec prefix.
+pub(crate) fn algorithm(key: &PatchedKeyPair) -> Result<&'static str, &'static str> {
+    let fingerprint = key.fingerprint();
+    let bytes = fingerprint
+        .strip_prefix('z')
+        .and_then(|fingerprint| fingerprint.from_base58().ok())
+        .ok_or("unsupported key")?;
+    match bytes.get(..2) {
+        Some([0xed, 0x01]) => Ok("EdDSA"),
+        Some([0x80, 0x24]) => Ok("ES256"),
+        _ => Err("unsupported key"),
+    }
+}
+
+/// Fails for keys other than Ed25519, the only keys data integrity proofs and signed answers are made with.
+pub(crate) fn check_ed25519(key: &PatchedKeyPair) -> Result<(), &'static str> {
+    match algorithm(key)? {
+        "EdDSA" => Ok(()),
+        _ => Err("unsupported key"),
+    }
+}
+
+/// Public key of a JWK of the `cnf` claim.
+fn key_of(jwk: &Value) -> Result<PatchedKeyPair, &'static str> {
+    let coordinate = |name: &str| {
+        jwk[name]
+            .as_str()
+            .and_then(|value| decode_config(value, URL_SAFE_NO_PAD).ok())
+            .ok_or("invalid key binding")
+    };
+    match (jwk["kty"].as_str(), jwk["crv"].as_str()) {
+        (Some("OKP"), Some("Ed25519")) => Ok(did_key::from_existing_key::<Ed25519KeyPair>(
+            &coordinate("x")?,
+            None,
+        )),
+        (Some("EC"), Some("P-256")) => {
+            let mut point = vec![0x04];
+            point.extend(coordinate("x")?);
+            point.extend(coordinate("y")?);
+            Ok(did_key::from_existing_key::<P256KeyPair>(&point, None))
+        }
+        _ => Err("unsupported key"),
+    }
+}
+
+fn did_of(kid: &str) -> &str {
+    kid.split('#').next().unwrap_or_default()
+}
+
+/// RFC 3339 date of a NumericDate claim.
+fn date(timestamp: &Value) -> Result<String, &'static str> {
+    timestamp
+        .as_i64()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .ok_or("invalid date")
+}
+
+fn timestamp(date: &str) -> Result<i64, &'static str> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.timestamp())
+        .map_err(|_| "invalid date")
+}
+
+fn digest(value: &str) -> String {
+    encode_config(Sha256::digest(value.as_bytes()), URL_SAFE_NO_PAD)
+}
+
+fn encode_json(value: &Value) -> String {
+    encode_config(value.to_string(), URL_SAFE_NO_PAD)
+}
+
+fn decode_json(encoded: &str) -> Result<Value, &'static str> {
+    let decoded = decode_config(encoded, URL_SAFE_NO_PAD).map_err(|_| "invalid encoding")?;
+    serde_json::from_slice(&decoded).map_err(|_| "invalid json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifiablecredential::DataModelVersion;
+    use did_key::{generate, Ed25519KeyPair, P256KeyPair};
+
+    #[test]
+    fn test_jwt_vc() {
+        let issuer = JwtIssuer::new(generate::<P256KeyPair>(Some(b"issuer")));
+        let holder = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder")));
+        let credential = VerifiableCredential::new(
+            DataModelVersion::V1_1,
+            &issuer.did(),
+            json!({ "id": holder.did(), "name": "Alice" }),
+        );
+        let jwt = issuer.sign_credential(&credential).unwrap();
+        assert_eq!(verify_credential(&jwt), Ok(credential));
+
+        let vp = holder
+            .sign_presentation(
+                &[jwt.to_string()],
+                Some("did:example:verifier"),
+                Some("1234"),
+            )
+            .unwrap();
+        assert_eq!(
+            verify_presentation(&vp, Some("did:example:verifier"), Some("1234"))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            verify_presentation(&vp, Some("did:example:verifier"), Some("5678")),
+            Err("invalid nonce")
+        );
+        assert_eq!(
+            verify_presentation(&vp, Some("did:example:other"), Some("1234")),
+            Err("invalid audience")
+        );
+        let other = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"other")));
+        let stolen = other
+            .sign_presentation(&[jwt], Some("did:example:verifier"), Some("1234"))
+            .unwrap();
+        assert_eq!(
+            verify_presentation(&stolen, Some("did:example:verifier"), Some("1234")),
+            Err("invalid holder")
+        );
+        let (input, _) = vp.rsplit_once('.').unwrap();
+        assert_eq!(
+            verify(&format!(
+                "{}.{}",
+                input,
+                encode_config([0; 64], URL_SAFE_NO_PAD)
+            )),
+            Err("invalid signature")
+        );
+    }
+
+    #[test]
+    fn test_sd_jwt() {
+        let issuer = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer")));
+        let holder = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder")));
+        let claims = json!({ "given_name": "Alice", "birthdate": "2000-01-01", "country": "DE" });
+        let issued = SdJwt::issue(
+            &issuer,
+            "https://example.com/identity",
+            claims.as_object().unwrap().clone(),
+            &["given_name", "birthdate"],
+            &holder.jwk().unwrap(),
+        )
+        .unwrap();
+        let issued: SdJwt = issued.to_string().parse().unwrap();
+        assert_eq!(issued.disclosures.len(), 2);
+
+        let presented = issued
+            .disclose(&["given_name"])
+            .bind(&holder, "did:example:verifier", "1234")
+            .unwrap();
+        let presented: SdJwt = presented.to_string().parse().unwrap();
+        let disclosed = presented.verify("did:example:verifier", "1234").unwrap();
+        assert_eq!(disclosed["given_name"], "Alice");
+        assert_eq!(disclosed["country"], "DE");
+        assert!(!disclosed.contains_key("birthdate"));
+        assert_eq!(
+            presented.verify("did:example:verifier", "5678"),
+            Err("invalid nonce")
+        );
+        assert_eq!(
+            presented.verify("did:example:other", "1234"),
+            Err("invalid audience")
+        );
+        assert_eq!(
+            issued.verify("did:example:verifier", "1234"),
+            Err("no key binding")
+        );
+        let mut unbound = presented.clone();
+        unbound.key_binding = Some(
+            holder
+                .sign(
+                    &json!({ "iat": Utc::now().timestamp(), "aud": "did:example:verifier", "nonce": "1234" }),
+                    "JWT",
+                )
+                .unwrap(),
+        );
+        assert_eq!(
+            unbound.verify("did:example:verifier", "1234"),
+            Err("invalid key binding")
+        );
+
+        let other = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"other")));
+        let stolen = issued
+            .disclose(&["given_name"])
+            .bind(&other, "did:example:verifier", "1234")
+            .unwrap();
+        assert_eq!(
+            stolen.verify("did:example:verifier", "1234"),
+            Err("invalid signature")
+        );
+
+        let mut forged = presented;
+        forged.disclosures = vec![Disclosure::new("birthdate", json!("1990-01-01"))];
+        assert_eq!(
+            forged.verify("did:example:verifier", "1234"),
+            Err("invalid disclosure")
+        );
+        assert_eq!(
+            SdJwt::issue(
+                &issuer,
+                "https://example.com/identity",
+                json!({ "given_name": "Alice", "cnf": { "jwk": {} } })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+                &[],
+                &holder.jwk().unwrap(),
+            ),
+            Err("reserved claim")
+        );
+    }
+}
@@ -1,17 +1,46 @@
+pub mod acks;
+pub mod actionmenu;
 pub mod basicmessage;
+pub mod connection;
+pub mod credentialmanifest;
+pub mod credentialstore;
+pub mod dataintegrity;
 pub mod didexchange;
+pub mod error;
+pub mod headers;
+pub mod healthmonitor;
 pub mod invitation;
 pub mod issuecredential;
+pub mod issuecredentialv3;
+pub mod jsonld;
+pub mod jwt;
+pub mod presentationexchange;
 pub mod presentproof;
+pub mod presentproofv3;
+pub mod questionanswer;
 pub mod reportproblem;
+pub mod revocationnotification;
 pub mod service;
+pub mod statuslist;
+pub mod storage;
+pub mod timing;
 pub mod trustping;
+mod v3message;
+pub mod verifiablecredential;
+pub mod waci;
 
+pub use acks::AckBuilder;
 pub use basicmessage::BasicMessageBuilder;
+pub use connection::Connection;
+pub use dataintegrity::{DataIntegrityIssuer, DataIntegrityVerifier};
 pub use didexchange::DidExchangeResponseBuilder;
+pub use error::ProtocolError;
+pub use healthmonitor::HealthMonitor;
 pub use invitation::InvitationBuilder;
 pub use issuecredential::*;
 pub use presentproof::PresentProofResponseBuilder;
 pub use reportproblem::ReportProblemResponseBuilder;
+pub use revocationnotification::RevocationNotificationBuilder;
 pub use service::Service;
 pub use trustping::TrustPingResponseBuilder;
+pub use verifiablecredential::{VerifiableCredential, VerifiablePresentation};
@@ -0,0 +1,348 @@
+//! # Presentation Exchange
+//!
+//! Presentation definitions of verifiers and presentation submissions of holders, evaluated against credentials in JSON.
+//! <https://identity.foundation/presentation-exchange/spec/v2.0.0/>
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0510-dif-pres-exch-attach/README.md>
+//!
+//! Fields are selected with JSONPath and filtered with JSON Schema, `submission_requirements` are not supported, every
+//! input descriptor has to be satisfied.
+
+use crate::verifiablecredential::VerifiablePresentation;
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// Context defining `presentation_submission` for signing presentations with embedded submissions.
+pub const CONTEXT_SUBMISSION: &str =
+    "https://identity.foundation/presentation-exchange/submission/v1";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresentationDefinition {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    /// claim formats and proof types the verifier accepts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+    pub input_descriptors: Vec<InputDescriptor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputDescriptor {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<Value>,
+    #[serde(default)]
+    pub constraints: Constraints,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Constraints {
+    /// `required` or `preferred`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_disclosure: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Field {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// JSONPath expressions, any of them selecting a value valid against the filter satisfies the field
+    pub path: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// JSON Schema the selected value has to be valid against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresentationSubmission {
+    pub id: String,
+    pub definition_id: String,
+    pub descriptor_map: Vec<DescriptorMapEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DescriptorMapEntry {
+    /// id of the input descriptor
+    pub id: String,
+    pub format: String,
+    /// JSONPath of the credential in the presentation
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_nested: Option<Box<DescriptorMapEntry>>,
+}
+
+impl Field {
+    /// True if a path selects a value valid against the filter.
+    pub fn matches(&self, credential: &Value) -> Result<bool, &'static str> {
+        let filter = match self.filter.as_ref() {
+            Some(filter) => Some(JSONSchema::compile(filter).map_err(|_| "invalid filter")?),
+            None => None,
+        };
+        for path in &self.path {
+            let values = jsonpath_lib::select(credential, path).map_err(|_| "invalid path")?;
+            if values.iter().any(|value| match filter.as_ref() {
+                Some(filter) => filter.is_valid(value),
+                None => true,
+            }) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl InputDescriptor {
+    /// True if all fields that are not optional match the credential.
+    pub fn matches(&self, credential: &Value) -> Result<bool, &'static str> {
+        for field in &self.constraints.fields {
+            if !field.optional && !field.matches(credential)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl PresentationDefinition {
+    /// Indices of the credentials matching each input descriptor, in the order of the descriptors.
+    pub fn evaluate(&self, credentials: &[Value]) -> Result<Vec<Vec<usize>>, &'static str> {
+        self.input_descriptors
+            .iter()
+            .map(|descriptor| {
+                let mut matches = Vec::new();
+                for (index, credential) in credentials.iter().enumerate() {
+                    if descriptor.matches(credential)? {
+                        matches.push(index);
+                    }
+                }
+                Ok(matches)
+            })
+            .collect()
+    }
+
+    /// Picks the first matching credential for every input descriptor and describes them in a submission for a
+    /// presentation with the picked credentials as `verifiableCredential`.
+    pub fn submit(
+        &self,
+        credentials: &[Value],
+        format: &str,
+    ) -> Result<(PresentationSubmission, Vec<Value>), &'static str> {
+        let mut selected: Vec<usize> = Vec::new();
+        let mut descriptor_map = Vec::new();
+        for (descriptor, matches) in self
+            .input_descriptors
+            .iter()
+            .zip(self.evaluate(credentials)?)
+        {
+            let index = *matches.first().ok_or("no matching credential")?;
+            let position = match selected.iter().position(|selected| *selected == index) {
+                Some(position) => position,
+                None => {
+                    selected.push(index);
+                    selected.len() - 1
+                }
+            };
+            descriptor_map.push(DescriptorMapEntry {
+                id: descriptor.id.to_string(),
+                format: format.to_string(),
+                path: format!("$.verifiableCredential[{}]", position),
+                path_nested: None,
+            });
+        }
+        let submission = PresentationSubmission {
+            id: Uuid::new_v4().to_string(),
+            definition_id: self.id.to_string(),
+            descriptor_map,
+        };
+        let credentials = selected
+            .iter()
+            .map(|index| credentials[*index].clone())
+            .collect();
+        Ok((submission, credentials))
+    }
+}
+
+impl PresentationSubmission {
+    /// Checks every input descriptor of the definition is mapped to a credential of the presentation satisfying it, in
+    /// a format the descriptor or else the definition accepts. Nested entries select the credential within the value
+    /// their parent entry selects, like a credential within an enveloped presentation.
+    pub fn verify(
+        &self,
+        definition: &PresentationDefinition,
+        presentation: &Value,
+    ) -> Result<(), &'static str> {
+        if self.definition_id != definition.id {
+            return Err("invalid definition");
+        }
+        for descriptor in &definition.input_descriptors {
+            let entry = self
+                .descriptor_map
+                .iter()
+                .find(|entry| entry.id == descriptor.id)
+                .ok_or("missing input descriptor")?;
+            let mut entry = entry;
+            let mut credential = select(presentation, &entry.path)?;
+            while let Some(nested) = entry.path_nested.as_deref() {
+                if nested.id != entry.id {
+                    return Err("invalid nested path");
+                }
+                entry = nested;
+                credential = select(credential, &entry.path)?;
+            }
+            let formats = descriptor.format.as_ref().or(definition.format.as_ref());
+            if formats.is_some_and(|formats| formats.get(&entry.format).is_none()) {
+                return Err("unsupported format");
+            }
+            if !descriptor.matches(credential)? {
+                return Err("input descriptor not satisfied");
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the submission to the presentation as `presentation_submission`, with its context.
+    pub fn embed(&self, presentation: &mut VerifiablePresentation) {
+        let context = json!(CONTEXT_SUBMISSION);
+        if !presentation.context.contains(&context) {
+            presentation.context.push(context);
+        }
+        presentation
+            .properties
+            .insert("presentation_submission".to_string(), json!(self));
+    }
+
+    /// Reads the submission embedded in the presentation.
+    pub fn from_presentation(presentation: &Value) -> Result<Self, &'static str> {
+        serde_json::from_value(presentation["presentation_submission"].clone())
+            .map_err(|_| "no presentation submission")
+    }
+}
+
+fn select<'a>(value: &'a Value, path: &str) -> Result<&'a Value, &'static str> {
+    jsonpath_lib::select(value, path)
+        .map_err(|_| "invalid path")?
+        .first()
+        .cloned()
+        .ok_or("missing credential")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifiablecredential::{DataModelVersion, VerifiableCredential};
+
+    fn definition() -> PresentationDefinition {
+        serde_json::from_value(json!({
+            "id": "32f54163-7166-48f1-93d8-ff217bdb0653",
+            "input_descriptors": [{
+                "id": "adult",
+                "purpose": "You have to be of age",
+                "constraints": {
+                    "fields": [
+                        {
+                            "path": ["$.credentialSubject.birthdate", "$.vc.credentialSubject.birthdate"],
+                            "filter": { "type": "string", "pattern": "^19" }
+                        },
+                        { "path": ["$.credentialSubject.nickname"], "optional": true }
+                    ]
+                }
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn credential(birthdate: &str) -> Value {
+        json!(VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            json!({ "id": "did:example:alice", "birthdate": birthdate })
+        ))
+    }
+
+    #[test]
+    fn test_evaluate_and_submit() {
+        let definition = definition();
+        let credentials = vec![credential("2010-01-01"), credential("1990-01-01")];
+        assert_eq!(definition.evaluate(&credentials), Ok(vec![vec![1]]));
+
+        let (submission, selected) = definition.submit(&credentials, "ldp_vc").unwrap();
+        assert_eq!(selected, vec![credentials[1].clone()]);
+        assert_eq!(
+            submission.descriptor_map[0].path,
+            "$.verifiableCredential[0]"
+        );
+        assert_eq!(
+            definition.submit(&credentials[..1], "ldp_vc").unwrap_err(),
+            "no matching credential"
+        );
+
+        // the first path selects a value not valid against the filter, the second one a valid value
+        let mut wrapped = credential("2010-01-01");
+        wrapped["vc"] = credential("1990-01-01");
+        assert_eq!(definition.evaluate(&[wrapped]), Ok(vec![vec![0]]));
+    }
+
+    #[test]
+    fn test_verify_submission() {
+        let definition = definition();
+        let credentials = vec![credential("1990-01-01")];
+        let (submission, selected) = definition.submit(&credentials, "ldp_vc").unwrap();
+        let mut presentation = VerifiablePresentation::new(
+            DataModelVersion::V2_0,
+            Some("did:example:alice"),
+            selected
+                .into_iter()
+                .map(|credential| serde_json::from_value(credential).unwrap())
+                .collect(),
+        );
+        submission.embed(&mut presentation);
+        let presentation = json!(presentation);
+
+        let submission = PresentationSubmission::from_presentation(&presentation).unwrap();
+        assert_eq!(submission.verify(&definition, &presentation), Ok(()));
+
+        let mut restricted = definition.clone();
+        restricted.format = Some(json!({ "jwt_vc": { "alg": ["EdDSA"] } }));
+        assert_eq!(
+            submission.verify(&restricted, &presentation),
+            Err("unsupported format")
+        );
+
+        let mut enveloped = submission.clone();
+        enveloped.descriptor_map[0] = DescriptorMapEntry {
+            id: "adult".to_string(),
+            format: "ldp_vp".to_string(),
+            path: "$".to_string(),
+            path_nested: Some(Box::new(submission.descriptor_map[0].clone())),
+        };
+        assert_eq!(enveloped.verify(&definition, &presentation), Ok(()));
+        enveloped.descriptor_map[0].path_nested.as_mut().unwrap().id = "other".to_string();
+        assert_eq!(
+            enveloped.verify(&definition, &presentation),
+            Err("invalid nested path")
+        );
+
+        let mut forged = presentation;
+        forged["verifiableCredential"][0]["credentialSubject"]["birthdate"] = json!("2010-01-01");
+        assert_eq!(
+            submission.verify(&definition, &forged),
+            Err("input descriptor not satisfied")
+        );
+    }
+}
@@ -5,9 +5,23 @@
 //! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0454-present-proof-v2/README.md>
 //! ![](https://github.com/hyperledger/aries-rfcs/raw/main/features/0454-present-proof-v2/presentation-choreography.png)
 
+use crate::connection::Connection;
+use crate::headers;
+use crate::issuecredential::formatted_attachments;
+use crate::v3message::payload;
+use crate::verifiablecredential::VerifiablePresentation;
 use base64::encode;
 use didcomm_rs::{AttachmentBuilder, AttachmentDataBuilder, Message};
-use serde_json::Value;
+use serde_json::{json, Value};
+
+/// Attachment formats of presentations
+/// <https://github.com/hyperledger/aries-rfcs/blob/main/features/0454-present-proof-v2/README.md#presentation-request-attachment-formats>
+pub mod formats {
+    pub const DIF_PRESENTATION_DEFINITION: &str = "dif/presentation-exchange/definitions@v1.0";
+    pub const DIF_PRESENTATION_SUBMISSION: &str = "dif/presentation-exchange/submission@v1.0";
+    pub const JWT_VP: &str = "jwt_vp_json";
+    pub const SD_JWT_VC: &str = "vc+sd-jwt";
+}
 
 /// Present Proof Response Builder
 ///
@@ -17,6 +31,13 @@ pub struct PresentProofResponseBuilder {
     goal_code: Option<String>,
     message: Option<Message>,
     attachments: Vec<Value>,
+    presentations: Vec<VerifiablePresentation>,
+    format: Option<String>,
+    nonce: Option<String>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
 }
 
 impl PresentProofResponseBuilder {
@@ -44,32 +65,145 @@ impl PresentProofResponseBuilder {
         self
     }
 
+    /// Attaches the presentation, it is validated when the message is built.
+    pub fn presentation(&mut self, presentation: VerifiablePresentation) -> &mut Self {
+        self.presentations.push(presentation);
+        self
+    }
+
+    /// Format of the attachments, see [formats]. Strings are attached as they are with a format, like JWTs.
+    pub fn format(&mut self, format: &str) -> &mut Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    /// Nonce the presentation has to be bound to, it is the `challenge` in the `options` of the request attachments.
+    pub fn nonce(&mut self, nonce: String) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build_request_presentation(&mut self) -> Result<Message, &'static str> {
+        let mut message =
+            Message::new().m_type("https://didcomm.org/present-proof/2.1/request-presentation");
+        if let Some(comment) = self.comment.as_ref() {
+            message = message.add_header_field("comment".to_string(), comment.to_string())
+        }
+        if let Some(goal_code) = self.goal_code.as_ref() {
+            message = message.add_header_field("goal_code".to_string(), goal_code.to_string())
+        }
+        let attachments = match self.nonce.as_ref() {
+            Some(nonce) => with_nonce(&self.attachments, nonce)?,
+            None => self.attachments.clone(),
+        };
+        message = self.attach(message, "request-presentation", &attachments);
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+
     pub fn build_presentation(&mut self) -> Result<Message, &'static str> {
         let mut message =
             Message::new().m_type("https://didcomm.org/present-proof/2.1/presentation");
-        for attachment in &self.attachments {
-            message.append_attachment(
-                AttachmentBuilder::new(true)
-                    .with_id("presentation")
-                    .with_media_type("application/json")
-                    .with_data(
-                        AttachmentDataBuilder::new()
-                            .with_link("")
-                            .with_encoded_payload(&encode(
-                                &serde_json::to_string(attachment).unwrap(),
-                            )),
-                    ),
-            );
+        let mut attachments = self.attachments.clone();
+        for presentation in &self.presentations {
+            presentation.validate()?;
+            attachments
+                .push(serde_json::to_value(presentation).map_err(|_| "invalid presentation")?);
+        }
+        message = self.attach(message, "presentation", &attachments);
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
         }
 
         Ok(message)
     }
+
+    fn attach(&self, mut message: Message, id: &str, attachments: &[Value]) -> Message {
+        for attachment in attachments {
+            let mut builder = AttachmentBuilder::new(true)
+                .with_id(id)
+                .with_media_type("application/json")
+                .with_data(
+                    AttachmentDataBuilder::new()
+                        .with_link("")
+                        .with_encoded_payload(&encode(payload(attachment, &self.format))),
+                );
+            if let Some(format) = self.format.as_ref() {
+                builder = builder.with_format(format);
+            }
+            message.append_attachment(builder);
+        }
+        if let Some(format) = self.format.as_ref() {
+            message = message.add_header_field(
+                "formats".to_string(),
+                json!([{ "attach_id": id, "format": format }]).to_string(),
+            );
+        }
+        message
+    }
+}
+
+/// Nonce of a request-presentation the presentation has to be bound to.
+pub fn request_nonce(message: &Message) -> Option<String> {
+    formatted_attachments(message)
+        .iter()
+        .filter_map(|(_, data)| serde_json::from_slice::<Value>(data).ok())
+        .find_map(|request| request["options"]["challenge"].as_str().map(String::from))
+}
+
+/// Sets the nonce as `challenge` in the `options` of the request attachments, like presentation definitions carry
+/// it, which needs at least one request attachment that is a JSON object.
+pub(crate) fn with_nonce(attachments: &[Value], nonce: &str) -> Result<Vec<Value>, &'static str> {
+    let mut attachments = attachments.to_vec();
+    let mut carried = false;
+    for attachment in attachments
+        .iter_mut()
+        .filter(|attachment| attachment.is_object())
+    {
+        if !attachment["options"].is_object() {
+            attachment["options"] = json!({});
+        }
+        attachment["options"]["challenge"] = json!(nonce);
+        carried = true;
+    }
+    if !carried {
+        return Err("no request for the nonce");
+    }
+    Ok(attachments)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::jwt::{JwtIssuer, SdJwt};
     use base64::decode;
+    use did_key::{generate, Ed25519KeyPair};
     use std::str::from_utf8;
 
     #[test]
@@ -100,4 +234,59 @@ mod tests {
         );
         println!("{}", serde_json::to_string_pretty(&response).unwrap());
     }
+
+    #[test]
+    fn test_sd_jwt_presentation() {
+        let issuer = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer")));
+        let holder = JwtIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder")));
+        let credential = SdJwt::issue(
+            &issuer,
+            "https://example.com/identity",
+            json!({ "given_name": "Alice", "birthdate": "2000-01-01" })
+                .as_object()
+                .unwrap()
+                .clone(),
+            &["given_name", "birthdate"],
+            &holder.jwk().unwrap(),
+        )
+        .unwrap();
+
+        let request = PresentProofResponseBuilder::new()
+            .format(formats::DIF_PRESENTATION_DEFINITION)
+            .attachment(
+                json!({ "presentation_definition": { "id": "1", "input_descriptors": [] } }),
+            )
+            .nonce("1234".to_string())
+            .build_request_presentation()
+            .unwrap();
+        assert_eq!(
+            PresentProofResponseBuilder::new()
+                .nonce("1234".to_string())
+                .build_request_presentation(),
+            Err("no request for the nonce")
+        );
+        let nonce = request_nonce(&request).unwrap();
+        let presented = credential
+            .disclose(&["given_name"])
+            .bind(&holder, "did:example:verifier", &nonce)
+            .unwrap();
+        let response = PresentProofResponseBuilder::new()
+            .format(formats::SD_JWT_VC)
+            .attachment(Value::String(presented.to_string()))
+            .build_presentation()
+            .unwrap();
+
+        let response: Value = serde_json::from_str(&response.as_raw_json().unwrap()).unwrap();
+        assert_eq!(response["attachments"][0]["format"], formats::SD_JWT_VC);
+        let attachment = decode(
+            response["attachments"][0]["data"]["base64"]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        let presented: SdJwt = from_utf8(&attachment).unwrap().parse().unwrap();
+        let claims = presented.verify("did:example:verifier", "1234").unwrap();
+        assert_eq!(claims["given_name"], "Alice");
+        assert!(!claims.contains_key("birthdate"));
+    }
 }
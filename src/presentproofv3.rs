@@ -0,0 +1,389 @@
+//! # Present Proof 3.0
+//!
+//! The DIDComm v2 version of present proof, with the fields in the body of the messages and the format on every
+//! attachment. Version 2.1 is in [crate::presentproof], messages convert between both versions so a verifier can
+//! handle old and new wallets alike: convert what is received with [to_v3] and answer with [to_v2] if needed.
+//! <https://didcomm.org/present-proof/3.0/>
+
+use crate::connection::Connection;
+use crate::issuecredential::formatted_attachments;
+use crate::presentproof::{request_nonce, with_nonce};
+use crate::v3message::V3Message;
+use crate::verifiablecredential::VerifiablePresentation;
+use didcomm_rs::Message;
+use serde_json::{json, Value};
+
+pub const PROPOSE_PRESENTATION: &str = "https://didcomm.org/present-proof/3.0/propose-presentation";
+pub const REQUEST_PRESENTATION: &str = "https://didcomm.org/present-proof/3.0/request-presentation";
+pub const PRESENTATION: &str = "https://didcomm.org/present-proof/3.0/presentation";
+
+const V2_PREFIX: &str = "https://didcomm.org/present-proof/2.";
+const V3_PREFIX: &str = "https://didcomm.org/present-proof/3.0/";
+
+/// Fields that are headers in version 2.x and in the body in version 3.0. The `nonce` of a request is in the
+/// `options` of its attachments in version 2.x.
+const BODY_FIELDS: &[&str] = &["goal_code", "comment", "will_confirm"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PresentProofVersion {
+    V2,
+    V3,
+}
+
+impl PresentProofVersion {
+    pub fn of(message: &Message) -> Result<Self, &'static str> {
+        let m_type = &message.get_didcomm_header().m_type;
+        if m_type.starts_with(V3_PREFIX) {
+            Ok(PresentProofVersion::V3)
+        } else if m_type.starts_with(V2_PREFIX) {
+            Ok(PresentProofVersion::V2)
+        } else {
+            Err("unsupported message")
+        }
+    }
+}
+
+/// Converts a 2.x message to 3.0, headers move to the body and the `formats` header to the attachments.
+pub fn to_v3(message: &Message) -> Result<Message, &'static str> {
+    if PresentProofVersion::of(message)? == PresentProofVersion::V3 {
+        return Ok(message.clone());
+    }
+    let mut fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+    let m_type = fields["type"].as_str().unwrap_or_default().to_string();
+    let name = m_type.rsplit('/').next().unwrap_or_default();
+    fields["type"] = json!(format!("{}{}", V3_PREFIX, name));
+
+    let mut body: Value = message
+        .get_body()
+        .ok()
+        .and_then(|body| serde_json::from_str(&body).ok())
+        .filter(Value::is_object)
+        .unwrap_or_else(|| json!({}));
+    for field in BODY_FIELDS {
+        if let Some(value) = fields.as_object_mut().unwrap().remove(*field) {
+            body[*field] = match (*field, value) {
+                ("will_confirm", Value::String(value)) => json!(value == "true"),
+                (_, value) => value,
+            };
+        }
+    }
+    let formats: Vec<Value> = fields
+        .as_object_mut()
+        .unwrap()
+        .remove("formats")
+        .and_then(|formats| match formats {
+            Value::String(formats) => serde_json::from_str(&formats).ok(),
+            formats => serde_json::from_value(formats).ok(),
+        })
+        .unwrap_or_default();
+    if let Some(attachments) = fields.get_mut("attachments").and_then(Value::as_array_mut) {
+        for attachment in attachments {
+            let format = formats
+                .iter()
+                .find(|format| format["attach_id"] == attachment["id"])
+                .and_then(|format| format["format"].as_str());
+            if let (Some(format), None) = (format, attachment.get("format")) {
+                attachment["format"] = json!(format);
+            }
+        }
+    }
+    let message: Message = serde_json::from_value(fields).map_err(|_| "invalid message")?;
+    if let Some(nonce) = request_nonce(&message) {
+        body["nonce"] = json!(nonce);
+    }
+    Ok(message.body(&body.to_string()))
+}
+
+/// Converts a 3.0 message to 2.1, body fields become headers and the attachment formats the `formats` header.
+pub fn to_v2(message: &Message) -> Result<Message, &'static str> {
+    if PresentProofVersion::of(message)? == PresentProofVersion::V2 {
+        return Ok(message.clone());
+    }
+    let mut fields = serde_json::to_value(message).map_err(|_| "invalid message")?;
+    let m_type = fields["type"].as_str().unwrap_or_default().to_string();
+    let name = m_type.rsplit('/').next().unwrap_or_default();
+    fields["type"] = json!(format!("https://didcomm.org/present-proof/2.1/{}", name));
+    let mut formats = Vec::new();
+    if let Some(attachments) = fields.get_mut("attachments").and_then(Value::as_array_mut) {
+        for attachment in attachments {
+            if let Some(format) = attachment.as_object_mut().unwrap().remove("format") {
+                formats.push(json!({ "attach_id": attachment["id"], "format": format }));
+            }
+        }
+    }
+    let body: Value = message
+        .get_body()
+        .ok()
+        .and_then(|body| serde_json::from_str(&body).ok())
+        .unwrap_or_default();
+    let mut message: Message = serde_json::from_value(fields).map_err(|_| "invalid message")?;
+    message = message.body("{}");
+    for field in BODY_FIELDS {
+        let value = match &body[*field] {
+            Value::Null => continue,
+            Value::String(value) => value.to_string(),
+            // booleans like `will_confirm` are headers as "true" or "false"
+            value => value.to_string(),
+        };
+        message = message.add_header_field(field.to_string(), value);
+    }
+    if !formats.is_empty() {
+        message = message.add_header_field("formats".to_string(), json!(formats).to_string());
+    }
+    Ok(message)
+}
+
+/// A received message of either version, in the shape of version 3.0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentProofV3 {
+    pub m_type: String,
+    pub version: PresentProofVersion,
+    pub thid: String,
+    pub goal_code: Option<String>,
+    pub comment: Option<String>,
+    pub will_confirm: bool,
+    pub nonce: Option<String>,
+    /// format and decoded data of the attachments
+    pub attachments: Vec<(Option<String>, Vec<u8>)>,
+}
+
+impl TryFrom<&Message> for PresentProofV3 {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let version = PresentProofVersion::of(message)?;
+        let message = to_v3(message)?;
+        let header = message.get_didcomm_header();
+        let body: Value = message
+            .get_body()
+            .ok()
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default();
+        let string = |name: &str| body[name].as_str().map(String::from);
+        Ok(PresentProofV3 {
+            m_type: header.m_type.to_string(),
+            version,
+            thid: header.thid.clone().unwrap_or_else(|| header.id.clone()),
+            goal_code: string("goal_code"),
+            comment: string("comment"),
+            will_confirm: body["will_confirm"].as_bool().unwrap_or(false),
+            nonce: string("nonce"),
+            attachments: formatted_attachments(&message),
+        })
+    }
+}
+
+/// Present Proof 3.0 Response Builder
+#[derive(Default)]
+pub struct PresentProofV3ResponseBuilder {
+    fields: V3Message,
+    will_confirm: bool,
+    nonce: Option<String>,
+}
+
+impl PresentProofV3ResponseBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn comment(&mut self, comment: String) -> &mut Self {
+        self.fields.comment = Some(comment);
+        self
+    }
+
+    pub fn goal_code(&mut self, goal_code: String) -> &mut Self {
+        self.fields.goal_code = Some(goal_code);
+        self
+    }
+
+    /// The verifier of a request will confirm the presentation with an ack.
+    pub fn will_confirm(&mut self, will_confirm: bool) -> &mut Self {
+        self.will_confirm = will_confirm;
+        self
+    }
+
+    /// Nonce of a request the presentation has to be bound to, it is also set as `challenge` in the `options` of the
+    /// request attachments for version 2.x.
+    pub fn nonce(&mut self, nonce: String) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Message to respond to, of version 2.x or 3.0.
+    pub fn message(&mut self, message: Message) -> &mut Self {
+        self.fields.message = Some(message);
+        self
+    }
+
+    /// Attaches data in the format, see [crate::presentproof::formats]. Strings are attached as they are.
+    pub fn attachment(&mut self, format: &str, attachment: Value) -> &mut Self {
+        self.fields
+            .attachments
+            .push((format.to_string(), attachment));
+        self
+    }
+
+    /// Attaches the presentation, it is validated when the message is built.
+    pub fn presentation(
+        &mut self,
+        format: &str,
+        presentation: VerifiablePresentation,
+    ) -> &mut Self {
+        self.attachment(format, json!(presentation))
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.fields.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.fields.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.fields.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.fields.please_ack = please_ack;
+        self
+    }
+
+    /// Builds the next message of the protocol for the received message, in the version of the received message.
+    pub fn build(&mut self) -> Result<Message, &'static str> {
+        let received = self.fields.message.as_ref().ok_or("no message")?;
+        let version = PresentProofVersion::of(received)?;
+        let message = match PresentProofV3::try_from(received)?.m_type.as_str() {
+            PROPOSE_PRESENTATION => self.build_request_presentation()?,
+            REQUEST_PRESENTATION => self.build_presentation()?,
+            _ => return Err("unsupported message"),
+        };
+        match version {
+            PresentProofVersion::V2 => to_v2(&message),
+            PresentProofVersion::V3 => Ok(message),
+        }
+    }
+
+    pub fn build_propose_presentation(&mut self) -> Result<Message, &'static str> {
+        self.fields.build(PROPOSE_PRESENTATION, self.fields.body())
+    }
+
+    pub fn build_request_presentation(&mut self) -> Result<Message, &'static str> {
+        let mut body = self.fields.body();
+        if self.will_confirm {
+            body.insert("will_confirm".to_string(), json!(true));
+        }
+        if let Some(nonce) = self.nonce.as_ref() {
+            body.insert("nonce".to_string(), json!(nonce));
+            let (formats, attachments): (Vec<String>, Vec<Value>) =
+                self.fields.attachments.iter().cloned().unzip();
+            self.fields.attachments = formats
+                .into_iter()
+                .zip(with_nonce(&attachments, nonce)?)
+                .collect();
+        }
+        self.fields.build(REQUEST_PRESENTATION, body)
+    }
+
+    pub fn build_presentation(&mut self) -> Result<Message, &'static str> {
+        for (_, attachment) in &self.fields.attachments {
+            if let Ok(presentation) =
+                serde_json::from_value::<VerifiablePresentation>(attachment.clone())
+            {
+                presentation.validate()?;
+            }
+        }
+        self.fields.build(PRESENTATION, self.fields.body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers;
+    use crate::presentproof::{formats, request_nonce};
+    use crate::PresentProofResponseBuilder;
+
+    #[test]
+    fn test_serve_v2_and_v3_wallets() {
+        let definition =
+            json!({ "presentation_definition": { "id": "1", "input_descriptors": [] } });
+        let request = PresentProofV3ResponseBuilder::new()
+            .attachment(formats::DIF_PRESENTATION_DEFINITION, definition.clone())
+            .will_confirm(true)
+            .nonce("1234".to_string())
+            .comment("Show your passport".to_string())
+            .build_request_presentation()
+            .unwrap();
+        assert_eq!(
+            PresentProofV3::try_from(&request).unwrap().nonce,
+            Some("1234".to_string())
+        );
+
+        let v2 = to_v2(&request).unwrap();
+        assert_eq!(
+            v2.get_didcomm_header().m_type,
+            "https://didcomm.org/present-proof/2.1/request-presentation"
+        );
+        assert_eq!(headers::get(&v2, "will_confirm"), Some("true".to_string()));
+        assert_eq!(request_nonce(&v2), Some("1234".to_string()));
+        assert_eq!(
+            headers::get(&v2, "comment"),
+            Some("Show your passport".to_string())
+        );
+        assert_eq!(to_v3(&v2).unwrap(), request);
+
+        let v2_presentation = PresentProofResponseBuilder::new()
+            .message(v2)
+            .format(formats::JWT_VP)
+            .attachment(json!("eyJhbGciOiJFZERTQSJ9.e30.c2ln"))
+            .build_presentation()
+            .unwrap();
+        let v3_presentation = PresentProofV3ResponseBuilder::new()
+            .message(request.clone())
+            .attachment(formats::JWT_VP, json!("eyJhbGciOiJFZERTQSJ9.e30.c2ln"))
+            .build()
+            .unwrap();
+        for presentation in [v2_presentation, v3_presentation] {
+            let received = PresentProofV3::try_from(&presentation).unwrap();
+            assert_eq!(received.m_type, PRESENTATION);
+            assert_eq!(
+                received.attachments,
+                vec![(
+                    Some(formats::JWT_VP.to_string()),
+                    b"eyJhbGciOiJFZERTQSJ9.e30.c2ln".to_vec()
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_answer_in_received_version() {
+        let proposal = to_v2(
+            &PresentProofV3ResponseBuilder::new()
+                .goal_code("kyc".to_string())
+                .build_propose_presentation()
+                .unwrap(),
+        )
+        .unwrap();
+        let request = PresentProofV3ResponseBuilder::new()
+            .message(proposal.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            PresentProofVersion::of(&request),
+            Ok(PresentProofVersion::V2)
+        );
+        assert_eq!(
+            request.get_didcomm_header().thid.as_ref(),
+            Some(&proposal.get_didcomm_header().id)
+        );
+        assert_eq!(
+            PresentProofV3::try_from(&proposal).unwrap().goal_code,
+            Some("kyc".to_string())
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! # Question Answer
+//!
+//! A questioner asks a question with a set of valid responses, the responder answers with one of them. If the question
+//! requires a signature, the answer is signed with an Ed25519 did:key over the question, the response and the nonce
+//! of the question, so the answer can serve as proof of consent.
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0113-question-answer/README.md>
+
+use crate::connection::Connection;
+use crate::headers;
+use crate::jwt::check_ed25519;
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use did_key::{CoreSign, Fingerprint, PatchedKeyPair};
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+pub const QUESTION: &str = "https://didcomm.org/questionanswer/1.0/question";
+pub const ANSWER: &str = "https://didcomm.org/questionanswer/1.0/answer";
+pub const SIGNATURE_TYPE: &str = "https://didcomm.org/signature/1.0/ed25519Sha512_single";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Question {
+    /// id of the question message, the thread of the answer
+    #[serde(skip)]
+    pub id: String,
+    pub question_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question_detail: Option<String>,
+    pub nonce: String,
+    #[serde(default)]
+    pub signature_required: bool,
+    pub valid_responses: Vec<ValidResponse>,
+    /// `expires_time` of the question message, it can not be answered afterwards
+    #[serde(skip)]
+    pub expires_time: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValidResponse {
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Answer {
+    #[serde(skip)]
+    pub thid: Option<String>,
+    pub response: String,
+    #[serde(rename = "response~sig", skip_serializing_if = "Option::is_none")]
+    pub response_sig: Option<ResponseSignature>,
+    /// time the answer message was read, in seconds since Unix Epoch
+    #[serde(skip)]
+    pub received_time: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseSignature {
+    #[serde(rename = "@type")]
+    pub type_: String,
+    /// base64url encoded signature of the signed data
+    pub signature: String,
+    /// base64url encoded signing time in seconds as 64 bit big endian integer, followed by the question text, the
+    /// response and the nonce
+    pub sig_data: String,
+    /// did:key of the responder
+    pub signer: String,
+}
+
+impl TryFrom<&Message> for Question {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if header.m_type != QUESTION {
+            return Err("unsupported message");
+        }
+        let body = message.get_body().map_err(|_| "invalid body")?;
+        let mut question: Question = serde_json::from_str(&body).map_err(|_| "invalid body")?;
+        question.id = header.id.clone();
+        question.expires_time = header.expires_time;
+        Ok(question)
+    }
+}
+
+impl TryFrom<&Message> for Answer {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if header.m_type != ANSWER {
+            return Err("unsupported message");
+        }
+        let body = message.get_body().map_err(|_| "invalid body")?;
+        let mut answer: Answer = serde_json::from_str(&body).map_err(|_| "invalid body")?;
+        answer.thid = header.thid.clone();
+        answer.received_time = Some(chrono::Utc::now().timestamp() as u64);
+        Ok(answer)
+    }
+}
+
+impl Question {
+    fn check_response(&self, response: &str) -> Result<(), &'static str> {
+        if !self
+            .valid_responses
+            .iter()
+            .any(|valid| valid.text == response)
+        {
+            return Err("invalid response");
+        }
+        Ok(())
+    }
+
+    /// Fails if the question expired before the time, in seconds since Unix Epoch.
+    fn check_expiry(&self, time: u64) -> Result<(), &'static str> {
+        match self.expires_time {
+            Some(expires_time) if expires_time < time => Err("question expired"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Data an answer with the response signs, without the signing time.
+    fn message_data(&self, response: &str) -> Vec<u8> {
+        [
+            self.question_text.as_bytes(),
+            response.as_bytes(),
+            self.nonce.as_bytes(),
+        ]
+        .concat()
+    }
+}
+
+impl Answer {
+    /// Checks the answer is a valid response to the question, received before the question expired, and verifies its
+    /// signature. The signing time in the signed data is chosen by the responder and not trusted for the expiry.
+    /// Returns the did of the signer if the answer is signed.
+    pub fn verify(&self, question: &Question) -> Result<Option<String>, &'static str> {
+        if self.thid.as_ref() != Some(&question.id) {
+            return Err("invalid thread");
+        }
+        question.check_response(&self.response)?;
+        question.check_expiry(
+            self.received_time
+                .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64),
+        )?;
+        let response_sig = match self.response_sig.as_ref() {
+            Some(response_sig) => response_sig,
+            None if question.signature_required => return Err("missing signature"),
+            None => return Ok(None),
+        };
+        if response_sig.type_ != SIGNATURE_TYPE {
+            return Err("unsupported signature");
+        }
+        let sig_data = decode_config(&response_sig.sig_data, URL_SAFE_NO_PAD)
+            .map_err(|_| "invalid signature")?;
+        if sig_data.len() < 8 || sig_data[8..] != question.message_data(&self.response)[..] {
+            return Err("invalid signature");
+        }
+        let key = did_key::resolve(&response_sig.signer).map_err(|_| "invalid signer")?;
+        check_ed25519(&key)?;
+        let signature = decode_config(&response_sig.signature, URL_SAFE_NO_PAD)
+            .map_err(|_| "invalid signature")?;
+        key.verify(&sig_data, &signature)
+            .map_err(|_| "invalid signature")?;
+        Ok(Some(response_sig.signer.to_string()))
+    }
+}
+
+/// Question Builder
+#[derive(Default)]
+pub struct QuestionBuilder {
+    question_text: Option<String>,
+    question_detail: Option<String>,
+    nonce: Option<String>,
+    signature_required: bool,
+    valid_responses: Vec<String>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+}
+
+impl QuestionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn question_text(&mut self, question_text: String) -> &mut Self {
+        self.question_text = Some(question_text);
+        self
+    }
+
+    pub fn question_detail(&mut self, question_detail: String) -> &mut Self {
+        self.question_detail = Some(question_detail);
+        self
+    }
+
+    /// Nonce the answer signs, defaults to a random one.
+    pub fn nonce(&mut self, nonce: String) -> &mut Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    pub fn signature_required(&mut self, signature_required: bool) -> &mut Self {
+        self.signature_required = signature_required;
+        self
+    }
+
+    pub fn valid_response(&mut self, text: String) -> &mut Self {
+        self.valid_responses.push(text);
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    /// Time the question expires, the responder should not answer afterwards.
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build(&mut self) -> Result<Message, &'static str> {
+        if self.valid_responses.is_empty() {
+            return Err("no valid responses");
+        }
+        let question = Question {
+            id: String::new(),
+            question_text: self.question_text.clone().ok_or("no question text")?,
+            question_detail: self.question_detail.clone(),
+            nonce: self
+                .nonce
+                .clone()
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            signature_required: self.signature_required,
+            valid_responses: self
+                .valid_responses
+                .iter()
+                .map(|text| ValidResponse {
+                    text: text.to_string(),
+                })
+                .collect(),
+            expires_time: self.expires_time,
+        };
+        let mut message = Message::new()
+            .m_type(QUESTION)
+            .body(&json!(question).to_string());
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+/// Answer Builder
+#[derive(Default)]
+pub struct AnswerBuilder {
+    message: Option<Message>,
+    response: Option<String>,
+    signer: Option<PatchedKeyPair>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+}
+
+impl AnswerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The question to answer.
+    pub fn message(&mut self, message: Message) -> &mut Self {
+        self.message = Some(message);
+        self
+    }
+
+    pub fn response(&mut self, response: String) -> &mut Self {
+        self.response = Some(response);
+        self
+    }
+
+    /// Ed25519 did:key key the answer is signed with.
+    pub fn signer(&mut self, signer: PatchedKeyPair) -> &mut Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build(&mut self) -> Result<Message, &'static str> {
+        let question = Question::try_from(self.message.as_ref().ok_or("no message")?)?;
+        let response = self.response.clone().ok_or("no response")?;
+        question.check_response(&response)?;
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+        question.check_expiry(timestamp)?;
+        let response_sig = match self.signer.as_ref() {
+            Some(signer) => {
+                check_ed25519(signer)?;
+                let sig_data = [
+                    timestamp.to_be_bytes().as_slice(),
+                    &question.message_data(&response),
+                ]
+                .concat();
+                Some(ResponseSignature {
+                    type_: SIGNATURE_TYPE.to_string(),
+                    signature: encode_config(signer.sign(&sig_data), URL_SAFE_NO_PAD),
+                    sig_data: encode_config(&sig_data, URL_SAFE_NO_PAD),
+                    signer: format!("did:key:{}", signer.fingerprint()),
+                })
+            }
+            None if question.signature_required => return Err("signature required"),
+            None => None,
+        };
+        let answer = Answer {
+            thid: None,
+            response,
+            response_sig,
+            received_time: None,
+        };
+        let mut message = Message::new()
+            .m_type(ANSWER)
+            .thid(&question.id)
+            .body(&json!(answer).to_string());
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use did_key::{generate, Ed25519KeyPair, P256KeyPair};
+
+    fn question(signature_required: bool) -> Message {
+        QuestionBuilder::new()
+            .question_text("Alice, are you on the phone with Bob from Faber Bank right now?".to_string())
+            .question_detail("This is optional fine-print giving context to the question and its various answers.".to_string())
+            .valid_response("Yes, it's me".to_string())
+            .valid_response("No, that's not me!".to_string())
+            .signature_required(signature_required)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signed_answer() {
+        let question_message = question(true);
+        let signer = generate::<Ed25519KeyPair>(Some(b"alice"));
+        let did = format!("did:key:{}", signer.fingerprint());
+        let answer_message = AnswerBuilder::new()
+            .message(question_message.clone())
+            .response("Yes, it's me".to_string())
+            .signer(signer)
+            .build()
+            .unwrap();
+
+        let question = Question::try_from(&question_message).unwrap();
+        let answer = Answer::try_from(&answer_message).unwrap();
+        assert_eq!(answer.verify(&question), Ok(Some(did)));
+
+        let mut forged = answer.clone();
+        forged.response = "No, that's not me!".to_string();
+        assert_eq!(forged.verify(&question), Err("invalid signature"));
+        let mut other = question.clone();
+        other.nonce = "other".to_string();
+        assert_eq!(answer.verify(&other), Err("invalid signature"));
+        let mut expired = question.clone();
+        expired.expires_time = Some(1);
+        assert_eq!(answer.verify(&expired), Err("question expired"));
+        expired.expires_time = Some(chrono::Utc::now().timestamp() as u64 + 60);
+        assert!(answer.verify(&expired).is_ok());
+        let mut late = answer.clone();
+        late.received_time = expired.expires_time.map(|expires_time| expires_time + 1);
+        assert_eq!(late.verify(&expired), Err("question expired"));
+
+        let expired = QuestionBuilder::new()
+            .question_text("Alice, are you on the phone with Bob?".to_string())
+            .valid_response("Yes, it's me".to_string())
+            .signature_required(true)
+            .expires_time(1)
+            .build()
+            .unwrap();
+        assert_eq!(
+            AnswerBuilder::new()
+                .message(expired)
+                .response("Yes, it's me".to_string())
+                .signer(generate::<Ed25519KeyPair>(Some(b"alice")))
+                .build()
+                .unwrap_err(),
+            "question expired"
+        );
+        assert_eq!(
+            AnswerBuilder::new()
+                .message(question_message)
+                .response("Yes, it's me".to_string())
+                .signer(generate::<P256KeyPair>(Some(b"alice")))
+                .build()
+                .unwrap_err(),
+            "unsupported key"
+        );
+    }
+
+    #[test]
+    fn test_unsigned_answer() {
+        let question_message = question(false);
+        let answer_message = AnswerBuilder::new()
+            .message(question_message.clone())
+            .response("No, that's not me!".to_string())
+            .build()
+            .unwrap();
+        let mut question = Question::try_from(&question_message).unwrap();
+        let answer = Answer::try_from(&answer_message).unwrap();
+        assert_eq!(answer.verify(&question), Ok(None));
+
+        question.signature_required = true;
+        assert_eq!(answer.verify(&question), Err("missing signature"));
+        assert_eq!(
+            AnswerBuilder::new()
+                .message(question_message)
+                .response("Maybe".to_string())
+                .build()
+                .unwrap_err(),
+            "invalid response"
+        );
+    }
+}
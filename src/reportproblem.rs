@@ -3,28 +3,431 @@
 //! Describes how to report errors and warnings in a powerful, interoperable way. All implementations of SSI agent or hub technology SHOULD implement this RFC.
 //! <https://identity.foundation/didcomm-messaging/spec/#problem-reports>
 
+use crate::connection::Connection;
+use crate::headers;
 use didcomm_rs::Message;
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Descriptors defined by the DIDComm Messaging spec.
+/// <https://identity.foundation/didcomm-messaging/spec/#descriptors>
+pub mod descriptors {
+    /// Failed to achieve required trust.
+    pub const TRUST: &str = "trust";
+    /// Cryptographically verifying the message failed.
+    pub const TRUST_CRYPTO: &str = "trust.crypto";
+    /// Unable to transport data.
+    pub const XFER: &str = "xfer";
+    /// A DID is unusable.
+    pub const DID: &str = "did";
+    /// Bad message, the message is malformed or not understood.
+    pub const MSG: &str = "msg";
+    /// Internal error of the sender.
+    pub const ME: &str = "me";
+    /// A required resource is unavailable.
+    pub const ME_RES: &str = "me.res";
+    /// Circumstances don't satisfy requirements.
+    pub const REQ: &str = "req";
+    /// Failed to satisfy timing constraints.
+    pub const REQ_TIME: &str = "req.time";
+    /// Failed for legal reasons.
+    pub const LEGAL: &str = "legal";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sorter {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scope {
+    /// The protocol is abandoned.
+    Protocol,
+    /// Only the message failed, the protocol continues.
+    Message,
+    /// The protocol goes back to the named state.
+    State(String),
+}
+
+/// A structured problem code like `e.p.xfer.cant-use-endpoint`.
+/// <https://identity.foundation/didcomm-messaging/spec/#problem-codes>
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemCode {
+    pub sorter: Sorter,
+    pub scope: Scope,
+    pub descriptors: Vec<String>,
+}
+
+impl ProblemCode {
+    /// Creates a code from a dot separated descriptor like `trust.crypto`.
+    pub fn new(sorter: Sorter, scope: Scope, descriptor: &str) -> Self {
+        ProblemCode {
+            sorter,
+            scope,
+            descriptors: descriptor.split('.').map(|d| d.to_string()).collect(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.sorter == Sorter::Error
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.sorter == Sorter::Warning
+    }
+
+    /// An error in protocol scope ends the whole protocol, other problems only affect one message or state.
+    pub fn aborts_protocol(&self) -> bool {
+        self.is_error() && self.scope == Scope::Protocol
+    }
+
+    pub fn descriptor(&self) -> String {
+        self.descriptors.join(".")
+    }
+
+    /// True if the descriptor is the given one or a more specific one, `trust.crypto` matches `trust`.
+    pub fn matches(&self, descriptor: &str) -> bool {
+        let own = self.descriptor();
+        own == descriptor || own.starts_with(&format!("{}.", descriptor))
+    }
+
+    /// Parses the code of a received problem report.
+    pub fn from_report(report: &Message) -> Result<Self, &'static str> {
+        if report.get_didcomm_header().m_type
+            != "https://didcomm.org/report-problem/2.0/problem-report"
+        {
+            return Err("unsupported message");
+        }
+        let body: Value = serde_json::from_str(&report.get_body().map_err(|_| "invalid body")?)
+            .map_err(|_| "invalid body")?;
+        body["code"].as_str().ok_or("missing code")?.parse()
+    }
+
+    fn is_token(token: &str) -> bool {
+        !token.is_empty()
+            && token
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    }
+}
+
+impl FromStr for ProblemCode {
+    type Err = &'static str;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let mut tokens = code.split('.');
+        let sorter = match tokens.next() {
+            Some("e") => Sorter::Error,
+            Some("w") => Sorter::Warning,
+            _ => return Err("invalid sorter"),
+        };
+        let scope = match tokens.next() {
+            Some("p") => Scope::Protocol,
+            Some("m") => Scope::Message,
+            Some(state) if Self::is_token(state) => Scope::State(state.to_string()),
+            _ => return Err("invalid scope"),
+        };
+        let descriptors: Vec<String> = tokens.map(|token| token.to_string()).collect();
+        if descriptors.is_empty() || !descriptors.iter().all(|d| Self::is_token(d)) {
+            return Err("invalid descriptor");
+        }
+        Ok(ProblemCode {
+            sorter,
+            scope,
+            descriptors,
+        })
+    }
+}
+
+impl fmt::Display for ProblemCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sorter = match self.sorter {
+            Sorter::Error => "e",
+            Sorter::Warning => "w",
+        };
+        let scope = match &self.scope {
+            Scope::Protocol => "p",
+            Scope::Message => "m",
+            Scope::State(state) => state,
+        };
+        write!(f, "{}.{}.{}", sorter, scope, self.descriptor())
+    }
+}
+
+/// A received problem report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemReport {
+    pub id: String,
+    pub thid: Option<String>,
+    pub pthid: Option<String>,
+    pub code: ProblemCode,
+    pub comment: Option<String>,
+    pub args: Vec<String>,
+    pub escalate_to: Option<String>,
+    /// ids of the messages the report acknowledges
+    pub ack: Vec<String>,
+    /// ids of the messages the sender wants to be acknowledged
+    pub please_ack: Vec<String>,
+}
+
+impl ProblemReport {
+    /// The comment with the args put in place of its `{1}`, `{2}`, ... placeholders.
+    pub fn render(&self) -> Option<String> {
+        self.comment
+            .as_ref()
+            .map(|comment| interpolate(comment, &self.args))
+    }
+
+    /// Renders the text of the catalog for the language and falls back to the comment of the report.
+    pub fn render_localized(&self, catalog: &ProblemCatalog, lang: &str) -> Option<String> {
+        match catalog.get(&self.code, lang) {
+            Some(template) => Some(interpolate(template, &self.args)),
+            None => self.render(),
+        }
+    }
+
+    /// True if the report is about the message, by its ack or else by its thread.
+    pub fn acknowledges(&self, message: &Message) -> bool {
+        let header = message.get_didcomm_header();
+        if !self.ack.is_empty() {
+            return self.ack.contains(&header.id);
+        }
+        let thid = header.thid.as_ref().unwrap_or(&header.id);
+        self.thid.as_ref() == Some(thid) || self.pthid.as_ref() == Some(thid)
+    }
+
+    /// What to do to escalate the report, if the sender asks for it.
+    pub fn escalation(&self) -> Option<Escalation> {
+        let escalate_to = self.escalate_to.as_ref()?;
+        if let Some(address) = escalate_to.strip_prefix("mailto:") {
+            return Some(Escalation::Mail {
+                to: address.to_string(),
+                subject: format!("Problem report {}", self.code),
+                body: self.render().unwrap_or_else(|| self.code.to_string()),
+            });
+        }
+        if escalate_to.starts_with("did:") {
+            let mut builder = ReportProblemResponseBuilder::new();
+            builder
+                .problem_code(self.code.clone())
+                .args(self.args.clone())
+                .pthid(self.thid.clone().unwrap_or_else(|| self.id.clone()));
+            if let Some(comment) = self.comment.as_ref() {
+                builder.comment(comment.to_string());
+            }
+            return builder
+                .build()
+                .ok()
+                .map(|message| Escalation::DidComm(Box::new(message.to(&[escalate_to]))));
+        }
+        None
+    }
+
+    /// Passes the escalation to the hook, returns false if the report asks for none.
+    pub fn escalate(&self, hook: &mut impl EscalationHook) -> Result<bool, &'static str> {
+        match self.escalation() {
+            Some(escalation) => hook.escalate(escalation).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Escalation a problem report asks for with its `escalate_to` field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Escalation {
+    Mail {
+        to: String,
+        subject: String,
+        body: String,
+    },
+    /// A problem report for the DID in `escalate_to`.
+    DidComm(Box<Message>),
+}
+
+/// Sends escalations, implemented for closures taking an [`Escalation`].
+pub trait EscalationHook {
+    fn escalate(&mut self, escalation: Escalation) -> Result<(), &'static str>;
+}
+
+impl<F> EscalationHook for F
+where
+    F: FnMut(Escalation) -> Result<(), &'static str>,
+{
+    fn escalate(&mut self, escalation: Escalation) -> Result<(), &'static str> {
+        self(escalation)
+    }
+}
+
+/// Remembers sent messages to find the ones received problem reports are about.
+#[derive(Default)]
+pub struct ProblemReportCorrelator {
+    sent: Vec<Message>,
+}
+
+impl ProblemReportCorrelator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&mut self, message: &Message) -> &mut Self {
+        self.sent.push(message.clone());
+        self
+    }
+
+    pub fn correlate(&self, report: &ProblemReport) -> Vec<&Message> {
+        self.sent
+            .iter()
+            .filter(|message| report.acknowledges(message))
+            .collect()
+    }
+
+    /// Stops tracking the messages the report is about and returns them.
+    pub fn resolve(&mut self, report: &ProblemReport) -> Vec<Message> {
+        let (resolved, sent) = self
+            .sent
+            .drain(..)
+            .partition(|message| report.acknowledges(message));
+        self.sent = sent;
+        resolved
+    }
+}
+
+impl TryFrom<&Message> for ProblemReport {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let code = ProblemCode::from_report(message)?;
+        let header = message.get_didcomm_header();
+        let body: Value = serde_json::from_str(&message.get_body().map_err(|_| "invalid body")?)
+            .map_err(|_| "invalid body")?;
+        let args = match body["args"].as_array() {
+            Some(args) => args
+                .iter()
+                .map(|arg| {
+                    arg.as_str()
+                        .map(String::from)
+                        .unwrap_or_else(|| arg.to_string())
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(ProblemReport {
+            id: header.id.clone(),
+            thid: header.thid.clone(),
+            pthid: header.pthid.clone(),
+            code,
+            comment: body["comment"].as_str().map(|c| c.to_string()),
+            args,
+            escalate_to: body["escalate_to"].as_str().map(|e| e.to_string()),
+            ack: headers::get_list(message, "ack"),
+            please_ack: headers::get_list(message, "please_ack"),
+        })
+    }
+}
+
+/// Replaces the placeholders `{1}`, `{2}`, ... with the args, placeholders without arg are kept.
+pub fn interpolate(template: &str, args: &[String]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let arg = rest.find('}').and_then(|end| {
+            rest[1..end]
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index > 0)
+                .and_then(|index| args.get(index - 1))
+                .map(|arg| (arg, end))
+        });
+        match arg {
+            Some((arg, end)) => {
+                rendered.push_str(arg);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Human readable texts for problem codes in several languages.
+#[derive(Default)]
+pub struct ProblemCatalog {
+    texts: HashMap<(String, String), String>,
+}
+
+impl ProblemCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a text with `{1}`-style placeholders for a code like `e.p.xfer` and a language like `en`.
+    pub fn add(&mut self, code: &str, lang: &str, text: &str) -> &mut Self {
+        self.texts
+            .insert((code.to_string(), lang.to_lowercase()), text.to_string());
+        self
+    }
+
+    /// Looks up the most specific text for the code, `de-CH` falls back to `de`
+    /// and `e.p.xfer.cant-use-endpoint` falls back to `e.p.xfer`.
+    pub fn get(&self, code: &ProblemCode, lang: &str) -> Option<&str> {
+        let lang = lang.to_lowercase();
+        let mut langs = vec![lang.as_str()];
+        if let Some((primary, _)) = lang.split_once('-') {
+            langs.push(primary);
+        }
+        let mut code = code.clone();
+        while !code.descriptors.is_empty() {
+            for lang in &langs {
+                if let Some(text) = self.texts.get(&(code.to_string(), lang.to_string())) {
+                    return Some(text);
+                }
+            }
+            code.descriptors.pop();
+        }
+        None
+    }
+}
 
 #[derive(Default)]
 pub struct ReportProblemResponseBuilder {
     message: Option<Message>,
+    thid: Option<String>,
+    pthid: Option<String>,
     code: Option<String>,
     comment: Option<String>,
     args: Vec<String>,
     escalate_to: Option<String>,
     ack: Vec<String>,
+    please_ack: bool,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
 }
 
 impl ReportProblemResponseBuilder {
     pub fn new() -> Self {
         ReportProblemResponseBuilder {
             message: None,
+            thid: None,
+            pthid: None,
             code: None,
             comment: None,
             args: Vec::new(),
             escalate_to: None,
             ack: Vec::new(),
+            please_ack: false,
+            connection: None,
+            created_time: None,
+            expires_time: None,
         }
     }
 
@@ -33,11 +436,28 @@ impl ReportProblemResponseBuilder {
         self
     }
 
+    /// Thread of the report, defaults to the id of the message the report is about.
+    pub fn thid(&mut self, thid: String) -> &mut Self {
+        self.thid = Some(thid);
+        self
+    }
+
+    /// Parent thread, the thread of the protocol that failed.
+    pub fn pthid(&mut self, pthid: String) -> &mut Self {
+        self.pthid = Some(pthid);
+        self
+    }
+
     pub fn code(&mut self, code: String) -> &mut Self {
         self.code = Some(code);
         self
     }
 
+    pub fn problem_code(&mut self, problem_code: ProblemCode) -> &mut Self {
+        self.code = Some(problem_code.to_string());
+        self
+    }
+
     pub fn comment(&mut self, comment: String) -> &mut Self {
         self.comment = Some(comment);
         self
@@ -58,16 +478,49 @@ impl ReportProblemResponseBuilder {
         self
     }
 
+    /// Asks the recipient to acknowledge the report.
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
     pub fn build(&mut self) -> Result<Message, &'static str> {
+        if self.code.is_none() {
+            return Err("no code");
+        }
         let mut message = Message::new()
             .m_type("https://didcomm.org/report-problem/2.0/problem-report")
             .body(&serde_json::to_string(&self.build_body()).unwrap());
-        if let Some(m) = self.message.as_ref() {
+        if let Some(thid) = self.thid.as_ref() {
+            message = message.thid(thid)
+        } else if let Some(m) = self.message.as_ref() {
             message = message.thid(&m.get_didcomm_header().id)
         }
+        if let Some(pthid) = self.pthid.as_ref() {
+            message = message.pthid(pthid)
+        }
         if !self.ack.is_empty() {
-            message = message
-                .add_header_field("ack".to_string(), serde_json::to_string(&self.ack).unwrap())
+            message = headers::add_list(message, "ack", &self.ack);
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
         }
         Ok(message)
     }
@@ -122,13 +575,10 @@ mod tests {
             .build()
             .unwrap();
 
-        let didcomm_message = response.as_raw_json().unwrap();
+        let didcomm_message = headers::as_raw_json(&response).unwrap();
 
         let json_value: Value = serde_json::from_str(&didcomm_message).unwrap();
-        assert_eq!(
-            json_value["ack"].as_str().unwrap(),
-            serde_json::to_string(&json!(vec!(thid))).unwrap()
-        );
+        assert_eq!(json_value["ack"].as_array(), Some(&vec![json!(thid)]));
         assert_eq!(json_value["body"]["code"].as_str().unwrap(), "c");
         assert_eq!(
             json_value["body"]["comment"].as_str().unwrap(),
@@ -138,5 +588,150 @@ mod tests {
             json_value["body"]["escalate_to"].as_str().unwrap(),
             "mailto:admin@foo.org"
         );
+
+        let received = headers::from_raw_json(&didcomm_message).unwrap();
+        assert_eq!(headers::get_list(&received, "ack"), vec![thid]);
+    }
+
+    #[test]
+    fn test_problem_code() {
+        let code: ProblemCode = "e.p.xfer.cant-use-endpoint".parse().unwrap();
+        assert!(code.is_error());
+        assert!(code.aborts_protocol());
+        assert!(code.matches(descriptors::XFER));
+        assert_eq!(code.descriptor(), "xfer.cant-use-endpoint");
+        assert_eq!(code.to_string(), "e.p.xfer.cant-use-endpoint");
+
+        let code: ProblemCode = "w.get-pay-details.req.time".parse().unwrap();
+        assert!(code.is_warning());
+        assert!(!code.aborts_protocol());
+        assert_eq!(code.scope, Scope::State("get-pay-details".to_string()));
+
+        assert_eq!("x.p.msg".parse::<ProblemCode>(), Err("invalid sorter"));
+        assert_eq!("e.p".parse::<ProblemCode>(), Err("invalid descriptor"));
+        assert_eq!("e.P.msg".parse::<ProblemCode>(), Err("invalid scope"));
+
+        let report = ReportProblemResponseBuilder::new()
+            .problem_code(ProblemCode::new(
+                Sorter::Error,
+                Scope::Message,
+                descriptors::TRUST_CRYPTO,
+            ))
+            .build()
+            .unwrap();
+        let code = ProblemCode::from_report(&report).unwrap();
+        assert_eq!(code.to_string(), "e.m.trust.crypto");
+        assert!(code.matches(descriptors::TRUST));
+        assert!(!code.matches("tru"));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let args = vec!["https://agents.r.us/inbox".to_string(), "2".to_string()];
+        assert_eq!(
+            interpolate("Unable to use the {1} endpoint for {2} times.", &args),
+            "Unable to use the https://agents.r.us/inbox endpoint for 2 times."
+        );
+        assert_eq!(interpolate("{3} {0} {x} {", &args), "{3} {0} {x} {");
+    }
+
+    #[test]
+    fn test_render_localized() {
+        let message = Message::new();
+        let report = ReportProblemResponseBuilder::new()
+            .message(message)
+            .code("e.p.xfer.cant-use-endpoint".to_string())
+            .comment("Unable to use the {1} endpoint.".to_string())
+            .args(vec!["https://agents.r.us/inbox".to_string()])
+            .build()
+            .unwrap();
+
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert_eq!(report.code.descriptor(), "xfer.cant-use-endpoint");
+        assert_eq!(
+            report.render().unwrap(),
+            "Unable to use the https://agents.r.us/inbox endpoint."
+        );
+
+        let mut catalog = ProblemCatalog::new();
+        catalog.add("e.p.xfer", "de", "Der Endpunkt {1} ist nicht nutzbar.");
+        assert_eq!(
+            report.render_localized(&catalog, "de-CH").unwrap(),
+            "Der Endpunkt https://agents.r.us/inbox ist nicht nutzbar."
+        );
+        assert_eq!(
+            report.render_localized(&catalog, "fr").unwrap(),
+            "Unable to use the https://agents.r.us/inbox endpoint."
+        );
+    }
+
+    #[test]
+    fn test_escalate() {
+        let report = ReportProblemResponseBuilder::new()
+            .code("e.p.me.res".to_string())
+            .comment("Out of {1}.".to_string())
+            .args(vec!["storage".to_string()])
+            .escalate_to("mailto:admin@foo.org".to_string())
+            .please_ack(true)
+            .build()
+            .unwrap();
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert_eq!(report.please_ack, vec![report.id.to_string()]);
+
+        let mut escalations = Vec::new();
+        let escalated = report
+            .escalate(&mut |escalation| {
+                escalations.push(escalation);
+                Ok(())
+            })
+            .unwrap();
+        assert!(escalated);
+        assert_eq!(
+            escalations,
+            vec![Escalation::Mail {
+                to: "admin@foo.org".to_string(),
+                subject: "Problem report e.p.me.res".to_string(),
+                body: "Out of storage.".to_string()
+            }]
+        );
+
+        let mut report = report;
+        report.escalate_to = Some("did:example:admin".to_string());
+        match report.escalation().unwrap() {
+            Escalation::DidComm(message) => {
+                assert_eq!(message.get_didcomm_header().to, vec!["did:example:admin"]);
+                assert_eq!(
+                    message.get_didcomm_header().pthid.as_ref(),
+                    Some(&report.id)
+                );
+            }
+            escalation => panic!("unexpected {:?}", escalation),
+        }
+    }
+
+    #[test]
+    fn test_correlate() {
+        let first = Message::new();
+        let second = Message::new().thid("thread");
+        let mut correlator = ProblemReportCorrelator::new();
+        correlator.track(&first).track(&second);
+
+        let report = ReportProblemResponseBuilder::new()
+            .message(first.clone())
+            .ack(vec![first.get_didcomm_header().id.to_string()])
+            .code("e.m.msg".to_string())
+            .build()
+            .unwrap();
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert_eq!(correlator.correlate(&report), vec![&first]);
+
+        let report = ReportProblemResponseBuilder::new()
+            .pthid("thread".to_string())
+            .code("e.p.msg".to_string())
+            .build()
+            .unwrap();
+        let report = ProblemReport::try_from(&report).unwrap();
+        assert_eq!(correlator.resolve(&report), vec![second]);
+        assert!(correlator.correlate(&report).is_empty());
     }
 }
@@ -0,0 +1,340 @@
+//! # Revocation Notification
+//!
+//! Lets an issuer tell the holder a credential was revoked. The notification refers to the credential by an id that
+//! depends on the revocation format, the holder matches it with the credentials it received.
+//! <https://github.com/hyperledger/aries-rfcs/blob/main/features/0721-revocation-notification-v2/README.md>
+
+use crate::connection::Connection;
+use crate::headers;
+use crate::issuecredential::credential_payloads;
+use crate::verifiablecredential::VerifiableCredential;
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const REVOKE: &str = "https://didcomm.org/revocation_notification/2.0/revoke";
+
+pub mod revocation_formats {
+    pub const INDY_ANONCREDS: &str = "indy-anoncreds";
+    pub const ANONCREDS: &str = "anoncreds";
+    /// the credential id is the id of the `BitstringStatusListEntry` of the credential
+    pub const BITSTRING_STATUS_LIST: &str = "bitstring-status-list";
+}
+
+/// A received revocation notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RevocationNotification {
+    pub revocation_format: String,
+    pub credential_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// thread of the issuance of the credential
+    #[serde(skip)]
+    pub pthid: Option<String>,
+}
+
+impl TryFrom<&Message> for RevocationNotification {
+    type Error = &'static str;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let header = message.get_didcomm_header();
+        if header.m_type != REVOKE {
+            return Err("unsupported message");
+        }
+        let body = message.get_body().map_err(|_| "invalid body")?;
+        let mut notification: RevocationNotification =
+            serde_json::from_str(&body).map_err(|_| "invalid body")?;
+        notification.pthid = header.pthid.clone();
+        Ok(notification)
+    }
+}
+
+/// The id a revocation notification uses for the credential, the id of its status list entry or else its own id.
+pub fn credential_id(credential: &VerifiableCredential) -> Option<String> {
+    let status = match credential.credential_status.as_ref() {
+        Some(Value::Array(entries)) => entries.first(),
+        status => status,
+    };
+    status
+        .and_then(|status| status["id"].as_str())
+        .or(credential.id.as_deref())
+        .map(String::from)
+}
+
+#[derive(Default)]
+pub struct RevocationNotificationBuilder {
+    revocation_format: Option<String>,
+    credential_id: Option<String>,
+    comment: Option<String>,
+    issue_thid: Option<String>,
+    connection: Option<Connection>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+}
+
+impl RevocationNotificationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn revocation_format(&mut self, revocation_format: &str) -> &mut Self {
+        self.revocation_format = Some(revocation_format.to_string());
+        self
+    }
+
+    pub fn credential_id(&mut self, credential_id: String) -> &mut Self {
+        self.credential_id = Some(credential_id);
+        self
+    }
+
+    /// Notifies about the credential, revoked with its bitstring status list entry.
+    pub fn credential(&mut self, credential: &VerifiableCredential) -> &mut Self {
+        self.revocation_format = Some(revocation_formats::BITSTRING_STATUS_LIST.to_string());
+        self.credential_id = credential_id(credential);
+        self
+    }
+
+    pub fn comment(&mut self, comment: String) -> &mut Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Thread of the issue-credential protocol the credential was issued in.
+    pub fn issue_thid(&mut self, issue_thid: String) -> &mut Self {
+        self.issue_thid = Some(issue_thid);
+        self
+    }
+
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
+    pub fn build(&mut self) -> Result<Message, &'static str> {
+        let mut body = json!({
+            "revocation_format": self.revocation_format.as_ref().ok_or("no revocation format")?,
+            "credential_id": self.credential_id.as_ref().ok_or("no credential id")?,
+        });
+        if let Some(comment) = self.comment.as_ref() {
+            body["comment"] = json!(comment);
+        }
+        let mut message = Message::new().m_type(REVOKE).body(&body.to_string());
+        if let Some(issue_thid) = self.issue_thid.as_ref() {
+            message = message.pthid(issue_thid);
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+/// A credential received by the holder in an issue-credential thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialRecord {
+    /// thread of the issue-credential protocol
+    pub thid: String,
+    /// DID of the issuer that sent the credential, the only one that can revoke it
+    pub from: String,
+    pub credential_id: Option<String>,
+    pub credential: VerifiableCredential,
+    pub revoked: bool,
+    pub revocation_comment: Option<String>,
+}
+
+/// Credentials of the holder, marked revoked by the notifications of their issuers.
+#[derive(Default)]
+pub struct CredentialRecords {
+    records: HashMap<String, Vec<CredentialRecord>>,
+}
+
+impl CredentialRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the credentials of an issue-credential message with its sender, returns the thread of the issuance.
+    /// Only JSON-LD credentials can be recorded, other credential attachments fail.
+    pub fn add_issued(&mut self, message: &Message) -> Result<String, &'static str> {
+        let header = message.get_didcomm_header();
+        let thid = header.thid.clone().unwrap_or_else(|| header.id.clone());
+        let from = header.from.clone().ok_or("no sender")?;
+        let records = credential_payloads(message)
+            .iter()
+            .map(|payload| {
+                let credential: VerifiableCredential =
+                    serde_json::from_slice(payload).map_err(|_| "unsupported credential format")?;
+                Ok(CredentialRecord {
+                    thid: thid.to_string(),
+                    from: from.to_string(),
+                    credential_id: credential_id(&credential),
+                    credential,
+                    revoked: false,
+                    revocation_comment: None,
+                })
+            })
+            .collect::<Result<Vec<CredentialRecord>, &'static str>>()?;
+        if records.is_empty() {
+            return Err("no credential");
+        }
+        self.records
+            .entry(thid.to_string())
+            .or_default()
+            .extend(records);
+        Ok(thid)
+    }
+
+    pub fn get(&self, thid: &str) -> Vec<&CredentialRecord> {
+        self.records
+            .get(thid)
+            .map(|records| records.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Marks the credentials the notification is about as revoked and returns them. Only credentials sent by the
+    /// sender of the notification match, by their id within the issue thread the notification refers to, or by the
+    /// issue thread alone.
+    pub fn revoke(&mut self, message: &Message) -> Result<Vec<&CredentialRecord>, &'static str> {
+        let notification = RevocationNotification::try_from(message)?;
+        let from = message
+            .get_didcomm_header()
+            .from
+            .as_ref()
+            .ok_or("no sender")?;
+        let mut revoked = Vec::new();
+        for records in self.records.values_mut() {
+            for record in records.iter_mut().filter(|record| &record.from == from) {
+                let in_thread = notification.pthid.as_ref() == Some(&record.thid);
+                let matches = match record.credential_id.as_ref() {
+                    Some(credential_id) => {
+                        credential_id == &notification.credential_id
+                            && (notification.pthid.is_none() || in_thread)
+                    }
+                    None => in_thread,
+                };
+                if matches {
+                    record.revoked = true;
+                    record.revocation_comment = notification.comment.clone();
+                    revoked.push(&*record);
+                }
+            }
+        }
+        if revoked.is_empty() {
+            return Err("unknown credential");
+        }
+        Ok(revoked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issuecredential::formats;
+    use crate::statuslist::StatusListManager;
+    use crate::verifiablecredential::DataModelVersion;
+    use crate::IssueCredentialResponseBuilder;
+
+    #[test]
+    fn test_revocation_notification() {
+        let mut manager = StatusListManager::new("https://example.com/status/1", "revocation");
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            json!({ "id": "did:example:alice" }),
+        );
+        manager.allocate(&mut credential).unwrap();
+        let issued = IssueCredentialResponseBuilder::new()
+            .credential(credential.clone())
+            .build_issue_credential()
+            .unwrap()
+            .from("did:example:issuer");
+        let mut records = CredentialRecords::new();
+        let thid = records.add_issued(&issued).unwrap();
+        let mut other = credential.clone();
+        manager.allocate(&mut other).unwrap();
+        let reissued = IssueCredentialResponseBuilder::new()
+            .credential(other)
+            .build_issue_credential()
+            .unwrap()
+            .from("did:example:issuer")
+            .thid(&thid);
+        records.add_issued(&reissued).unwrap();
+        assert_eq!(records.get(&thid).len(), 2);
+        let jwt = IssueCredentialResponseBuilder::new()
+            .format(formats::JWT_VC)
+            .attachment(json!("eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl"))
+            .build_issue_credential()
+            .unwrap()
+            .from("did:example:issuer");
+        assert_eq!(
+            records.add_issued(&jwt),
+            Err("unsupported credential format")
+        );
+
+        let notification = RevocationNotificationBuilder::new()
+            .credential(&credential)
+            .issue_thid("other-thread".to_string())
+            .build()
+            .unwrap()
+            .from("did:example:issuer");
+        assert_eq!(
+            records.revoke(&notification).unwrap_err(),
+            "unknown credential"
+        );
+        let notification = RevocationNotificationBuilder::new()
+            .credential(&credential)
+            .comment("Lost".to_string())
+            .issue_thid(thid.to_string())
+            .please_ack(true)
+            .build()
+            .unwrap();
+        assert_eq!(records.revoke(&notification).unwrap_err(), "no sender");
+        assert_eq!(
+            records
+                .revoke(&notification.clone().from("did:example:mallory"))
+                .unwrap_err(),
+            "unknown credential"
+        );
+        let notification = notification.from("did:example:issuer");
+        let parsed = RevocationNotification::try_from(&notification).unwrap();
+        assert_eq!(parsed.credential_id, "https://example.com/status/1#0");
+        assert_eq!(parsed.pthid, Some(thid.to_string()));
+        assert_eq!(
+            headers::get_list(&notification, "please_ack"),
+            vec![notification.get_didcomm_header().id.to_string()]
+        );
+
+        let revoked = records.revoke(&notification).unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].revocation_comment, Some("Lost".to_string()));
+        assert!(records.get(&thid)[0].revoked);
+        assert!(!records.get(&thid)[1].revoked);
+
+        let unknown = RevocationNotificationBuilder::new()
+            .revocation_format(revocation_formats::ANONCREDS)
+            .credential_id("rev_reg::1".to_string())
+            .build()
+            .unwrap()
+            .from("did:example:issuer");
+        assert_eq!(records.revoke(&unknown).unwrap_err(), "unknown credential");
+    }
+}
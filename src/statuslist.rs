@@ -0,0 +1,275 @@
+//! # Status List
+//!
+//! Revocation and suspension of issued credentials with a Bitstring Status List. The issuer allocates a position in the
+//! list for every credential and publishes the list as signed credential, verifiers check the bit of the credential in
+//! a status list credential they already have.
+//! <https://www.w3.org/TR/vc-bitstring-status-list/>
+
+use crate::dataintegrity::DataIntegrityIssuer;
+use crate::verifiablecredential::{DataModelVersion, VerifiableCredential};
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+
+/// Smallest list size in bits, so a single credential can not be told from the others.
+pub const MIN_LIST_SIZE: usize = 131_072;
+
+pub struct StatusListManager {
+    url: String,
+    purpose: String,
+    bits: Vec<u8>,
+    next_index: usize,
+}
+
+impl StatusListManager {
+    /// Creates an empty list published at the url, with a purpose like `revocation` or `suspension`.
+    pub fn new(url: &str, purpose: &str) -> Self {
+        StatusListManager {
+            url: url.to_string(),
+            purpose: purpose.to_string(),
+            bits: vec![0; MIN_LIST_SIZE / 8],
+            next_index: 0,
+        }
+    }
+
+    /// Restores a list from the `encodedList` of its published credential.
+    pub fn from_encoded(
+        url: &str,
+        purpose: &str,
+        encoded_list: &str,
+        next_index: usize,
+    ) -> Result<Self, &'static str> {
+        Ok(StatusListManager {
+            url: url.to_string(),
+            purpose: purpose.to_string(),
+            bits: decode_list(encoded_list)?,
+            next_index,
+        })
+    }
+
+    /// Allocates the next index of the list and adds the status entry to the credential.
+    pub fn allocate(
+        &mut self,
+        credential: &mut VerifiableCredential,
+    ) -> Result<usize, &'static str> {
+        let index = self.next_index;
+        if index >= self.bits.len() * 8 {
+            return Err("status list full");
+        }
+        self.next_index += 1;
+        credential.credential_status = Some(json!({
+            "id": format!("{}#{}", self.url, index),
+            "type": "BitstringStatusListEntry",
+            "statusPurpose": self.purpose,
+            "statusListIndex": index.to_string(),
+            "statusListCredential": self.url,
+        }));
+        Ok(index)
+    }
+
+    /// Sets the status of the credential at the index, `true` revokes or suspends it.
+    pub fn set(&mut self, index: usize, status: bool) -> Result<(), &'static str> {
+        if index >= self.next_index {
+            return Err("index not allocated");
+        }
+        set_bit(&mut self.bits, index, status);
+        Ok(())
+    }
+
+    pub fn status(&self, index: usize) -> bool {
+        get_bit(&self.bits, index)
+    }
+
+    /// Index the next credential gets, to be stored with the encoded list.
+    pub fn next_index(&self) -> usize {
+        self.next_index
+    }
+
+    pub fn encoded_list(&self) -> Result<String, &'static str> {
+        encode_list(&self.bits)
+    }
+
+    /// Builds the signed status list credential to be published at the url of the list.
+    pub fn credential(
+        &self,
+        issuer: &DataIntegrityIssuer,
+    ) -> Result<VerifiableCredential, &'static str> {
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            &issuer.did(),
+            json!({
+                "id": format!("{}#list", self.url),
+                "type": "BitstringStatusList",
+                "statusPurpose": self.purpose,
+                "encodedList": self.encoded_list()?,
+            }),
+        );
+        credential.id = Some(self.url.to_string());
+        credential
+            .type_
+            .push("BitstringStatusListCredential".to_string());
+        issuer.sign(&credential)
+    }
+}
+
+/// Checks the status entries of the credential against the status list credentials, which have to be verified
+/// already. Only lists of the issuer of the credential are used. A set bit fails with `credential revoked` or
+/// `credential suspended` by the purpose of the entry.
+pub fn check_status<'a>(
+    credential: &VerifiableCredential,
+    status_lists: impl IntoIterator<Item = &'a VerifiableCredential>,
+) -> Result<(), &'static str> {
+    let status_lists: Vec<&VerifiableCredential> = status_lists
+        .into_iter()
+        .filter(|list| list.issuer.id() == credential.issuer.id())
+        .collect();
+    let entries = match credential.credential_status.as_ref() {
+        Some(Value::Array(entries)) => entries.iter().collect(),
+        Some(entry) => vec![entry],
+        None => Vec::new(),
+    };
+    for entry in entries {
+        if entry["type"] != "BitstringStatusListEntry" {
+            continue;
+        }
+        let url = entry["statusListCredential"]
+            .as_str()
+            .ok_or("invalid status entry")?;
+        let index: usize = entry["statusListIndex"]
+            .as_str()
+            .and_then(|index| index.parse().ok())
+            .ok_or("invalid status entry")?;
+        let list = status_lists
+            .iter()
+            .copied()
+            .find(|list| list.id.as_deref() == Some(url))
+            .ok_or("unknown status list")?;
+        if !list
+            .type_
+            .iter()
+            .any(|t| t == "BitstringStatusListCredential")
+            || list.credential_subject["statusPurpose"] != entry["statusPurpose"]
+        {
+            return Err("invalid status list");
+        }
+        let bits = decode_list(
+            list.credential_subject["encodedList"]
+                .as_str()
+                .ok_or("invalid status list")?,
+        )?;
+        if index >= bits.len() * 8 {
+            return Err("invalid status entry");
+        }
+        if get_bit(&bits, index) {
+            return Err(match entry["statusPurpose"].as_str() {
+                Some("suspension") => "credential suspended",
+                _ => "credential revoked",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The first index is the most significant bit of the first byte.
+fn get_bit(bits: &[u8], index: usize) -> bool {
+    bits.get(index / 8)
+        .map(|byte| byte & (0x80 >> (index % 8)) != 0)
+        .unwrap_or(false)
+}
+
+fn set_bit(bits: &mut [u8], index: usize, status: bool) {
+    if status {
+        bits[index / 8] |= 0x80 >> (index % 8);
+    } else {
+        bits[index / 8] &= !(0x80 >> (index % 8));
+    }
+}
+
+/// GZIP compressed and multibase base64url encoded.
+fn encode_list(bits: &[u8]) -> Result<String, &'static str> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bits).map_err(|_| "invalid status list")?;
+    let compressed = encoder.finish().map_err(|_| "invalid status list")?;
+    Ok(format!("u{}", encode_config(compressed, URL_SAFE_NO_PAD)))
+}
+
+fn decode_list(encoded_list: &str) -> Result<Vec<u8>, &'static str> {
+    let compressed = decode_config(
+        encoded_list.strip_prefix('u').unwrap_or(encoded_list),
+        URL_SAFE_NO_PAD,
+    )
+    .map_err(|_| "invalid status list")?;
+    let mut bits = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut bits)
+        .map_err(|_| "invalid status list")?;
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataintegrity::DataIntegrityVerifier;
+    use did_key::{generate, Ed25519KeyPair};
+
+    #[test]
+    fn test_revoke_credential() {
+        let issuer = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap();
+        let mut manager = StatusListManager::new("https://example.com/status/1", "revocation");
+        let mut credential = VerifiableCredential::new(
+            DataModelVersion::V2_0,
+            &issuer.did(),
+            json!({ "id": "did:example:alice" }),
+        );
+        let index = manager.allocate(&mut credential).unwrap();
+        assert_eq!(
+            credential.credential_status.as_ref().unwrap()["statusListIndex"],
+            "0"
+        );
+        let credential = issuer.sign(&credential).unwrap();
+
+        let mut verifier = DataIntegrityVerifier::new();
+        verifier
+            .status_list(manager.credential(&issuer).unwrap())
+            .unwrap();
+        assert_eq!(verifier.verify(&credential), Ok(()));
+
+        manager.set(index, true).unwrap();
+        assert!(manager.status(index));
+        let list = manager.credential(&issuer).unwrap();
+        assert_eq!(
+            check_status(&credential, std::slice::from_ref(&list)),
+            Err("credential revoked")
+        );
+        verifier.status_list(list).unwrap();
+        assert_eq!(verifier.verify(&credential), Err("credential revoked"));
+        assert_eq!(
+            DataIntegrityVerifier::new().verify(&credential),
+            Err("unknown status list")
+        );
+
+        // a list at the same url signed by another issuer neither replaces the list nor revokes for it
+        let other = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"other"))).unwrap();
+        let cleared = StatusListManager::new("https://example.com/status/1", "revocation");
+        let forged = cleared.credential(&other).unwrap();
+        assert_eq!(
+            check_status(&credential, std::slice::from_ref(&forged)),
+            Err("unknown status list")
+        );
+        verifier.status_list(forged).unwrap();
+        assert_eq!(verifier.verify(&credential), Err("credential revoked"));
+
+        let restored = StatusListManager::from_encoded(
+            "https://example.com/status/1",
+            "revocation",
+            &manager.encoded_list().unwrap(),
+            manager.next_index(),
+        )
+        .unwrap();
+        assert!(restored.status(index));
+        assert_eq!(manager.set(5, true), Err("index not allocated"));
+    }
+}
@@ -0,0 +1,46 @@
+//! # Storage
+//!
+//! Key value storage for state the protocols need to keep between messages, like the ids of messages already received.
+
+use std::collections::HashMap;
+
+pub trait Storage {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&mut self, key: &str, value: String);
+    fn remove(&mut self, key: &str) -> Option<String>;
+    /// Keys starting with the prefix.
+    fn keys(&self, prefix: &str) -> Vec<String>;
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: HashMap<String, String>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<String> {
+        self.values.remove(key)
+    }
+
+    fn keys(&self, prefix: &str) -> Vec<String> {
+        self.values
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,186 @@
+//! # Message Timing
+//!
+//! Checks the `created_time` and `expires_time` headers of received messages and guards against replayed messages.
+//! <https://identity.foundation/didcomm-messaging/spec/#message-headers>
+
+use crate::storage::Storage;
+use chrono::{DateTime, Duration, Utc};
+use didcomm_rs::Message;
+
+/// Rejects expired messages and messages created in the future.
+pub struct TimingValidator {
+    clock_skew: Duration,
+}
+
+impl Default for TimingValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimingValidator {
+    pub fn new() -> Self {
+        TimingValidator {
+            clock_skew: Duration::seconds(30),
+        }
+    }
+
+    /// Tolerated difference between the clocks of sender and recipient.
+    pub fn clock_skew(&mut self, clock_skew: Duration) -> &mut Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    pub fn validate(&self, message: &Message) -> Result<(), &'static str> {
+        self.validate_at(message, Utc::now())
+    }
+
+    pub fn validate_at(&self, message: &Message, now: DateTime<Utc>) -> Result<(), &'static str> {
+        let header = message.get_didcomm_header();
+        if let Some(expires_time) = header.expires_time {
+            if (expires_time as i64) < (now - self.clock_skew).timestamp() {
+                return Err("message expired");
+            }
+        }
+        if let Some(created_time) = header.created_time {
+            if created_time as i64 > (now + self.clock_skew).timestamp() {
+                return Err("message created in the future");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rejects messages with an id received before within the window.
+///
+/// Messages created before the window can not be told apart from replays and are rejected as well.
+pub struct ReplayGuard<S: Storage> {
+    storage: S,
+    window: Duration,
+}
+
+impl<S: Storage> ReplayGuard<S> {
+    const PREFIX: &'static str = "replay:";
+
+    pub fn new(storage: S) -> Self {
+        ReplayGuard {
+            storage,
+            window: Duration::minutes(10),
+        }
+    }
+
+    pub fn window(&mut self, window: Duration) -> &mut Self {
+        self.window = window;
+        self
+    }
+
+    pub fn check(&mut self, message: &Message) -> Result<(), &'static str> {
+        self.check_at(message, Utc::now())
+    }
+
+    /// Remembers the id of the message if it was not received before.
+    pub fn check_at(&mut self, message: &Message, now: DateTime<Utc>) -> Result<(), &'static str> {
+        let header = message.get_didcomm_header();
+        let oldest = (now - self.window).timestamp();
+        if let Some(created_time) = header.created_time {
+            if (created_time as i64) < oldest {
+                return Err("message too old");
+            }
+        }
+        let key = format!("{}{}", Self::PREFIX, header.id);
+        if let Some(received) = self.storage.get(&key) {
+            if received.parse::<i64>().map_or(true, |time| time >= oldest) {
+                return Err("replayed message");
+            }
+        }
+        self.storage.set(&key, now.timestamp().to_string());
+        Ok(())
+    }
+
+    /// Forgets ids received before the window.
+    pub fn prune(&mut self, now: DateTime<Utc>) -> &mut Self {
+        let oldest = (now - self.window).timestamp();
+        for key in self.storage.keys(Self::PREFIX) {
+            let expired = match self.storage.get(&key).map(|time| time.parse::<i64>()) {
+                Some(Ok(time)) => time < oldest,
+                _ => true,
+            };
+            if expired {
+                self.storage.remove(&key);
+            }
+        }
+        self
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::BasicMessageBuilder;
+
+    #[test]
+    fn test_validate_timing() {
+        let now = Utc::now();
+        let validator = TimingValidator::new();
+        let message = BasicMessageBuilder::new()
+            .message("Hello World".to_string())
+            .expires_time((now + Duration::seconds(10)).timestamp() as u64)
+            .build()
+            .unwrap();
+        assert_eq!(validator.validate_at(&message, now), Ok(()));
+        assert_eq!(
+            validator.validate_at(&message, now + Duration::seconds(35)),
+            Ok(())
+        );
+        assert_eq!(
+            validator.validate_at(&message, now + Duration::seconds(50)),
+            Err("message expired")
+        );
+
+        let message = BasicMessageBuilder::new()
+            .message("Hello World".to_string())
+            .created_time((now + Duration::minutes(5)).timestamp() as u64)
+            .build()
+            .unwrap();
+        assert_eq!(
+            validator.validate_at(&message, now),
+            Err("message created in the future")
+        );
+        assert_eq!(
+            TimingValidator::new()
+                .clock_skew(Duration::minutes(10))
+                .validate_at(&message, now),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_replay_guard() {
+        let now = Utc::now();
+        let mut guard = ReplayGuard::new(MemoryStorage::new());
+        guard.window(Duration::minutes(1));
+        let message = BasicMessageBuilder::new()
+            .message("Hello World".to_string())
+            .created_time(now.timestamp() as u64)
+            .build()
+            .unwrap();
+
+        assert_eq!(guard.check_at(&message, now), Ok(()));
+        assert_eq!(
+            guard.check_at(&message, now + Duration::seconds(30)),
+            Err("replayed message")
+        );
+        assert_eq!(
+            guard.check_at(&message, now + Duration::minutes(2)),
+            Err("message too old")
+        );
+
+        guard.prune(now + Duration::minutes(2));
+        assert!(guard.storage().keys("replay:").is_empty());
+    }
+}
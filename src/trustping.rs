@@ -3,13 +3,28 @@
 //! The trust-ping protocol defined in the DIDComm Messaging Spec. This enables the sender and recipient to engage in an exchange of trust pings.
 //! <https://identity.foundation/didcomm-messaging/spec/#trust-ping-protocol-20>
 
+use crate::connection::Connection;
+use crate::headers;
+use chrono::{DateTime, Duration, Utc};
 use didcomm_rs::Message;
-use serde_json::json;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 
-#[derive(Default)]
 pub struct TrustPingResponseBuilder {
     thid: Option<String>,
     message: Option<Message>,
+    connection: Option<Connection>,
+    response_requested: bool,
+    comment: Option<String>,
+    created_time: Option<u64>,
+    expires_time: Option<u64>,
+    please_ack: bool,
+}
+
+impl Default for TrustPingResponseBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TrustPingResponseBuilder {
@@ -17,6 +32,12 @@ impl TrustPingResponseBuilder {
         TrustPingResponseBuilder {
             thid: None,
             message: None,
+            connection: None,
+            response_requested: true,
+            comment: None,
+            created_time: None,
+            expires_time: None,
+            please_ack: false,
         }
     }
 
@@ -30,10 +51,51 @@ impl TrustPingResponseBuilder {
         self
     }
 
+    pub fn connection(&mut self, connection: Connection) -> &mut Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn response_requested(&mut self, response_requested: bool) -> &mut Self {
+        self.response_requested = response_requested;
+        self
+    }
+
+    pub fn comment(&mut self, comment: String) -> &mut Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    pub fn created_time(&mut self, created_time: u64) -> &mut Self {
+        self.created_time = Some(created_time);
+        self
+    }
+
+    /// Time in seconds since Unix Epoch after which the ping should not be answered.
+    pub fn expires_time(&mut self, expires_time: u64) -> &mut Self {
+        self.expires_time = Some(expires_time);
+        self
+    }
+
+    pub fn please_ack(&mut self, please_ack: bool) -> &mut Self {
+        self.please_ack = please_ack;
+        self
+    }
+
     pub fn build(&mut self) -> Result<Message, &'static str> {
         match &self.message {
             Some(message) => match message.get_didcomm_header().m_type.as_str() {
-                "https://didcomm.org/trust-ping/2.0/ping" => self.build_response(),
+                "https://didcomm.org/trust-ping/2.0/ping" => {
+                    if let Some(expires_time) = message.get_didcomm_header().expires_time {
+                        if expires_time < Utc::now().timestamp() as u64 {
+                            return Err("message expired");
+                        }
+                    }
+                    if !Self::is_response_requested(message) {
+                        return Err("no response requested");
+                    }
+                    self.build_response()
+                }
                 _ => Err("unsupported message"),
             },
             None => self.build_ping(),
@@ -41,19 +103,107 @@ impl TrustPingResponseBuilder {
     }
 
     pub fn build_ping(&mut self) -> Result<Message, &'static str> {
-        Ok(Message::new()
+        let mut body = json!({ "response_requested": self.response_requested });
+        if let Some(comment) = self.comment.as_ref() {
+            body["comment"] = json!(comment);
+        }
+        let mut message = Message::new()
             .m_type("https://didcomm.org/trust-ping/2.0/ping")
-            .body(&json!({"response_requested": true}).to_string()))
+            .body(&body.to_string());
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
     }
 
     pub fn build_response(&mut self) -> Result<Message, &'static str> {
-        Ok(Message::new()
+        let mut message = Message::new()
             .m_type("https://didcomm.org/trust-ping/2.0/ping-response")
             .thid(
                 self.thid
                     .as_ref()
                     .unwrap_or_else(|| &self.message.as_ref().unwrap().get_didcomm_header().id),
-            ))
+            );
+        message = headers::timed(message, self.created_time, None)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+
+    /// A ping without body or without the flag asks for a response, as the flag defaults to true.
+    pub fn is_response_requested(ping: &Message) -> bool {
+        ping.get_body()
+            .ok()
+            .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+            .and_then(|body| body["response_requested"].as_bool())
+            .unwrap_or(true)
+    }
+}
+
+/// Correlates ping-responses with the pings sent, to measure round-trip time and find unanswered pings.
+#[derive(Default)]
+pub struct PingTracker {
+    pending: HashMap<String, DateTime<Utc>>,
+}
+
+impl PingTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&mut self, ping: &Message) -> &mut Self {
+        self.track_at(ping, Utc::now())
+    }
+
+    pub fn track_at(&mut self, ping: &Message, sent_time: DateTime<Utc>) -> &mut Self {
+        self.pending
+            .insert(ping.get_didcomm_header().id.clone(), sent_time);
+        self
+    }
+
+    /// Returns the round-trip time of the ping the response answers.
+    pub fn receive(&mut self, response: &Message) -> Result<Duration, &'static str> {
+        self.receive_at(response, Utc::now())
+    }
+
+    pub fn receive_at(
+        &mut self,
+        response: &Message,
+        received_time: DateTime<Utc>,
+    ) -> Result<Duration, &'static str> {
+        let header = response.get_didcomm_header();
+        if header.m_type != "https://didcomm.org/trust-ping/2.0/ping-response" {
+            return Err("unsupported message");
+        }
+        let thid = header.thid.as_ref().ok_or("missing thid")?;
+        let sent_time = self.pending.remove(thid).ok_or("unknown ping")?;
+        Ok(received_time - sent_time)
+    }
+
+    pub fn is_pending(&self, id: &str) -> bool {
+        self.pending.contains_key(id)
+    }
+
+    /// Ids of pings without response for longer than the timeout.
+    pub fn unanswered(&self, timeout: Duration) -> Vec<String> {
+        self.unanswered_at(timeout, Utc::now())
+    }
+
+    pub fn unanswered_at(&self, timeout: Duration, now: DateTime<Utc>) -> Vec<String> {
+        self.pending
+            .iter()
+            .filter(|(_, sent_time)| now - **sent_time > timeout)
+            .map(|(id, _)| id.to_string())
+            .collect()
+    }
+
+    /// Stops waiting for the ping with the given id.
+    pub fn forget(&mut self, id: &str) -> Option<DateTime<Utc>> {
+        self.pending.remove(id)
     }
 }
 
@@ -104,4 +254,58 @@ mod tests {
 
         println!("{}", serde_json::to_string_pretty(&response).unwrap());
     }
+
+    #[test]
+    fn test_no_response_requested() {
+        let ping = TrustPingResponseBuilder::new()
+            .response_requested(false)
+            .comment("just checking".to_string())
+            .build()
+            .unwrap();
+        assert!(!TrustPingResponseBuilder::is_response_requested(&ping));
+
+        let response = TrustPingResponseBuilder::new().message(ping).build();
+        assert_eq!(response.unwrap_err(), "no response requested");
+    }
+
+    #[test]
+    fn test_expired_ping() {
+        let ping = TrustPingResponseBuilder::new()
+            .expires_time(1)
+            .build()
+            .unwrap();
+        assert_eq!(ping.get_didcomm_header().expires_time, Some(1));
+
+        let response = TrustPingResponseBuilder::new().message(ping).build();
+        assert_eq!(response.unwrap_err(), "message expired");
+    }
+
+    #[test]
+    fn test_ping_tracker() {
+        let sent_time = Utc::now();
+        let ping = TrustPingResponseBuilder::new().build().unwrap();
+        let unanswered = TrustPingResponseBuilder::new().build().unwrap();
+        let mut tracker = PingTracker::new();
+        tracker
+            .track_at(&ping, sent_time)
+            .track_at(&unanswered, sent_time);
+
+        let response = TrustPingResponseBuilder::new()
+            .message(ping)
+            .build()
+            .unwrap();
+        let round_trip = tracker
+            .receive_at(&response, sent_time + Duration::milliseconds(42))
+            .unwrap();
+        assert_eq!(round_trip, Duration::milliseconds(42));
+        assert!(tracker.receive(&response).is_err());
+
+        assert_eq!(
+            tracker.unanswered_at(Duration::seconds(5), sent_time + Duration::seconds(10)),
+            vec![unanswered.get_didcomm_header().id.clone()]
+        );
+        assert!(tracker
+            .unanswered_at(Duration::seconds(5), sent_time)
+            .is_empty());
+    }
 }
@@ -0,0 +1,75 @@
+//! # 3.0 messages
+//!
+//! Fields and building of the messages of the DIDComm v2 protocols Issue Credential 3.0 and Present Proof 3.0, which
+//! keep their fields in the body and tell the format on every attachment.
+
+use crate::connection::Connection;
+use crate::headers;
+use base64::encode;
+use didcomm_rs::{AttachmentBuilder, AttachmentDataBuilder, Message};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+/// Fields the builders of the 3.0 protocols share, which keep `goal_code` and `comment` in the body and the format on
+/// every attachment.
+#[derive(Default)]
+pub(crate) struct V3Message {
+    pub comment: Option<String>,
+    pub goal_code: Option<String>,
+    /// received message the built one answers in its thread
+    pub message: Option<Message>,
+    pub attachments: Vec<(String, Value)>,
+    pub connection: Option<Connection>,
+    pub created_time: Option<u64>,
+    pub expires_time: Option<u64>,
+    pub please_ack: bool,
+}
+
+impl V3Message {
+    /// The body with `goal_code` and `comment` if they are set.
+    pub fn body(&self) -> Map<String, Value> {
+        let mut body = Map::new();
+        if let Some(goal_code) = self.goal_code.as_ref() {
+            body.insert("goal_code".to_string(), json!(goal_code));
+        }
+        if let Some(comment) = self.comment.as_ref() {
+            body.insert("comment".to_string(), json!(comment));
+        }
+        body
+    }
+
+    pub fn build(&self, m_type: &str, body: Map<String, Value>) -> Result<Message, &'static str> {
+        let mut message = Message::new()
+            .m_type(m_type)
+            .body(&Value::Object(body).to_string());
+        if let Some(received) = self.message.as_ref() {
+            let header = received.get_didcomm_header();
+            message = message.thid(header.thid.as_ref().unwrap_or(&header.id));
+        }
+        for (format, attachment) in &self.attachments {
+            message.append_attachment(
+                AttachmentBuilder::new(true)
+                    .with_id(&Uuid::new_v4().to_string())
+                    .with_format(format)
+                    .with_media_type("application/json")
+                    .with_data(AttachmentDataBuilder::new().with_encoded_payload(&encode(
+                        payload(attachment, &Some(format.to_string())),
+                    ))),
+            );
+        }
+        message = headers::timed(message, self.created_time, self.expires_time)?;
+        message = headers::please_ack(message, self.please_ack);
+        if let Some(connection) = self.connection.as_ref() {
+            message = connection.address(message);
+        }
+        Ok(message)
+    }
+}
+
+/// Data of an attachment, strings with a format are attached as they are, like JWTs.
+pub(crate) fn payload(attachment: &Value, format: &Option<String>) -> String {
+    match (attachment, format) {
+        (Value::String(attachment), Some(_)) => attachment.to_string(),
+        _ => serde_json::to_string(attachment).unwrap(),
+    }
+}
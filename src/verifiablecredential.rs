@@ -0,0 +1,410 @@
+//! # Verifiable Credential
+//!
+//! Data model of verifiable credentials and presentations issued and presented with the credential protocols.
+//! Supports version 1.1 and 2.0 of the data model.
+//! <https://www.w3.org/TR/vc-data-model/>
+//! <https://www.w3.org/TR/vc-data-model-2.0/>
+
+use crate::issuecredential::CredentialPreview;
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Map, Value};
+
+pub const CONTEXT_V1: &str = "https://www.w3.org/2018/credentials/v1";
+pub const CONTEXT_V2: &str = "https://www.w3.org/ns/credentials/v2";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataModelVersion {
+    V1_1,
+    V2_0,
+}
+
+impl DataModelVersion {
+    pub fn context(&self) -> &'static str {
+        match self {
+            DataModelVersion::V1_1 => CONTEXT_V1,
+            DataModelVersion::V2_0 => CONTEXT_V2,
+        }
+    }
+
+    fn from_context(context: &[Value]) -> Result<Self, &'static str> {
+        match context.first().and_then(|context| context.as_str()) {
+            Some(CONTEXT_V1) => Ok(DataModelVersion::V1_1),
+            Some(CONTEXT_V2) => Ok(DataModelVersion::V2_0),
+            _ => Err("invalid context"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum Issuer {
+    Id(String),
+    Object {
+        id: String,
+        #[serde(flatten)]
+        properties: Map<String, Value>,
+    },
+}
+
+impl Issuer {
+    pub fn id(&self) -> &str {
+        match self {
+            Issuer::Id(id) => id,
+            Issuer::Object { id, .. } => id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiableCredential {
+    #[serde(rename = "@context", deserialize_with = "one_or_many")]
+    pub context: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", deserialize_with = "one_or_many")]
+    pub type_: Vec<String>,
+    pub issuer: Issuer,
+    /// required by version 1.1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuance_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_date: Option<String>,
+    /// version 2.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<String>,
+    /// an object or an array of objects with the claims about the subjects
+    pub credential_subject: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_status: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_schema: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<Value>,
+    /// further properties like `evidence` or `termsOfUse`
+    #[serde(flatten)]
+    pub properties: Map<String, Value>,
+}
+
+impl VerifiableCredential {
+    /// Creates an unsigned credential, valid from now on.
+    pub fn new(version: DataModelVersion, issuer: &str, credential_subject: Value) -> Self {
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let (issuance_date, valid_from) = match version {
+            DataModelVersion::V1_1 => (Some(now), None),
+            DataModelVersion::V2_0 => (None, Some(now)),
+        };
+        VerifiableCredential {
+            context: vec![json!(version.context())],
+            id: None,
+            type_: vec!["VerifiableCredential".to_string()],
+            issuer: Issuer::Id(issuer.to_string()),
+            issuance_date,
+            expiration_date: None,
+            valid_from,
+            valid_until: None,
+            credential_subject,
+            credential_status: None,
+            credential_schema: None,
+            proof: None,
+            properties: Map::new(),
+        }
+    }
+
+    /// Creates a credential with the attributes of the preview as claims about the subject.
+    pub fn from_preview(
+        version: DataModelVersion,
+        issuer: &str,
+        subject_id: Option<&str>,
+        preview: &CredentialPreview,
+    ) -> Self {
+        let mut subject = Map::new();
+        if let Some(subject_id) = subject_id {
+            subject.insert("id".to_string(), json!(subject_id));
+        }
+        for attribute in &preview.attributes {
+            subject.insert(attribute.name.to_string(), json!(attribute.value));
+        }
+        Self::new(version, issuer, Value::Object(subject))
+    }
+
+    pub fn version(&self) -> Result<DataModelVersion, &'static str> {
+        DataModelVersion::from_context(&self.context)
+    }
+
+    /// Checks the required fields are present and well formed.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let version = self.version()?;
+        if !self.type_.iter().any(|t| t == "VerifiableCredential") {
+            return Err("invalid type");
+        }
+        if !self.issuer.id().contains(':') {
+            return Err("invalid issuer");
+        }
+        let subjects = match &self.credential_subject {
+            Value::Array(subjects) => subjects.iter().collect(),
+            subject => vec![subject],
+        };
+        if subjects.is_empty() || !subjects.iter().all(|subject| subject.is_object()) {
+            return Err("invalid credential subject");
+        }
+        let (from, until) = match version {
+            DataModelVersion::V1_1 => (
+                Some(self.issuance_date.as_ref().ok_or("missing issuance date")?),
+                self.expiration_date.as_ref(),
+            ),
+            DataModelVersion::V2_0 => (self.valid_from.as_ref(), self.valid_until.as_ref()),
+        };
+        let from = from.map(|from| parse_date(from)).transpose()?;
+        let until = until.map(|until| parse_date(until)).transpose()?;
+        if let (Some(from), Some(until)) = (from, until) {
+            if until < from {
+                return Err("invalid validity period");
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the credential and checks it is valid at the given time.
+    pub fn validate_at(&self, now: DateTime<Utc>) -> Result<(), &'static str> {
+        self.validate()?;
+        let from = self.issuance_date.as_ref().or(self.valid_from.as_ref());
+        let until = self.expiration_date.as_ref().or(self.valid_until.as_ref());
+        if let Some(from) = from {
+            if parse_date(from)? > now {
+                return Err("credential not yet valid");
+            }
+        }
+        if let Some(until) = until {
+            if parse_date(until)? < now {
+                return Err("credential expired");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifiablePresentation {
+    #[serde(rename = "@context", deserialize_with = "one_or_many")]
+    pub context: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", deserialize_with = "one_or_many")]
+    pub type_: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub holder: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub verifiable_credential: Vec<PresentedCredential>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<Value>,
+    #[serde(flatten)]
+    pub properties: Map<String, Value>,
+}
+
+impl VerifiablePresentation {
+    pub fn new(
+        version: DataModelVersion,
+        holder: Option<&str>,
+        verifiable_credential: Vec<VerifiableCredential>,
+    ) -> Self {
+        VerifiablePresentation {
+            context: vec![json!(version.context())],
+            id: None,
+            type_: vec!["VerifiablePresentation".to_string()],
+            holder: holder.map(|holder| holder.to_string()),
+            verifiable_credential: verifiable_credential
+                .into_iter()
+                .map(|credential| PresentedCredential::Embedded(Box::new(credential)))
+                .collect(),
+            proof: None,
+            properties: Map::new(),
+        }
+    }
+
+    pub fn version(&self) -> Result<DataModelVersion, &'static str> {
+        DataModelVersion::from_context(&self.context)
+    }
+
+    pub fn validate(&self) -> Result<(), &'static str> {
+        self.version()?;
+        if !self.type_.iter().any(|t| t == "VerifiablePresentation") {
+            return Err("invalid type");
+        }
+        for credential in self.embedded_credentials() {
+            credential.validate()?;
+        }
+        Ok(())
+    }
+
+    /// The credentials embedded as objects, without the encoded ones.
+    pub fn embedded_credentials(&self) -> impl Iterator<Item = &VerifiableCredential> {
+        self.verifiable_credential
+            .iter()
+            .filter_map(|credential| match credential {
+                PresentedCredential::Embedded(credential) => Some(credential.as_ref()),
+                PresentedCredential::Encoded(_) => None,
+            })
+    }
+}
+
+/// A credential of a presentation, embedded as object or encoded as string like a JWT.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum PresentedCredential {
+    Embedded(Box<VerifiableCredential>),
+    Encoded(String),
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, &'static str> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| "invalid date")
+}
+
+/// Accepts a single value where the data model allows a value or an array.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issuecredential::CredentialAttribute;
+    use chrono::Duration;
+
+    #[test]
+    fn test_parse_credential() {
+        let credential: VerifiableCredential = serde_json::from_value(json!({
+          "@context": [
+            "https://www.w3.org/2018/credentials/v1",
+            "https://www.w3.org/2018/credentials/examples/v1"
+          ],
+          "id": "http://example.edu/credentials/1872",
+          "type": ["VerifiableCredential", "AlumniCredential"],
+          "issuer": { "id": "https://example.edu/issuers/565049", "name": "Example University" },
+          "issuanceDate": "2010-01-01T19:23:24Z",
+          "credentialSubject": {
+            "id": "did:example:ebfeb1f712ebc6f1c276e12ec21",
+            "alumniOf": "Example University"
+          },
+          "evidence": [{ "id": "https://example.edu/evidence/f2aeec97" }]
+        }))
+        .unwrap();
+
+        assert_eq!(credential.version(), Ok(DataModelVersion::V1_1));
+        assert_eq!(credential.issuer.id(), "https://example.edu/issuers/565049");
+        assert!(credential.properties.contains_key("evidence"));
+        assert_eq!(credential.validate(), Ok(()));
+
+        let mut invalid = credential.clone();
+        invalid.issuance_date = None;
+        assert_eq!(invalid.validate(), Err("missing issuance date"));
+        let mut invalid = credential;
+        invalid.type_ = vec!["AlumniCredential".to_string()];
+        assert_eq!(invalid.validate(), Err("invalid type"));
+    }
+
+    #[test]
+    fn test_credential_from_preview() {
+        let preview = CredentialPreview {
+            type_: "https://didcomm.org/issue-credential/2.1/credential-preview".to_string(),
+            attributes: vec![CredentialAttribute::new(
+                "name".to_string(),
+                "Alice".to_string(),
+            )],
+        };
+        let mut credential = VerifiableCredential::from_preview(
+            DataModelVersion::V2_0,
+            "did:example:issuer",
+            Some("did:example:alice"),
+            &preview,
+        );
+        assert_eq!(credential.credential_subject["name"], "Alice");
+        assert_eq!(credential.validate_at(Utc::now()), Ok(()));
+
+        credential.valid_until = Some("2000-01-01T00:00:00Z".to_string());
+        assert_eq!(credential.validate(), Err("invalid validity period"));
+        credential.valid_from = None;
+        assert_eq!(
+            credential.validate_at(Utc::now()),
+            Err("credential expired")
+        );
+
+        let presentation = VerifiablePresentation::new(
+            DataModelVersion::V2_0,
+            Some("did:example:alice"),
+            vec![VerifiableCredential::from_preview(
+                DataModelVersion::V2_0,
+                "did:example:issuer",
+                None,
+                &preview,
+            )],
+        );
+        assert_eq!(presentation.validate(), Ok(()));
+        let json = serde_json::to_value(&presentation).unwrap();
+        assert_eq!(
+            json["verifiableCredential"][0]["credentialSubject"]["name"],
+            "Alice"
+        );
+        assert!(VerifiableCredential::from_preview(
+            DataModelVersion::V1_1,
+            "did:example:issuer",
+            None,
+            &preview
+        )
+        .validate_at(Utc::now() - Duration::days(1))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_presented_credentials() {
+        let credential = json!({
+            "@context": ["https://www.w3.org/ns/credentials/v2"],
+            "type": "VerifiableCredential",
+            "issuer": "did:example:issuer",
+            "credentialSubject": { "id": "did:example:alice" }
+        });
+        let presentation: VerifiablePresentation = serde_json::from_value(json!({
+            "@context": ["https://www.w3.org/ns/credentials/v2"],
+            "type": "VerifiablePresentation",
+            "verifiableCredential": credential
+        }))
+        .unwrap();
+        assert_eq!(presentation.embedded_credentials().count(), 1);
+
+        let presentation: VerifiablePresentation = serde_json::from_value(json!({
+            "@context": ["https://www.w3.org/ns/credentials/v2"],
+            "type": "VerifiablePresentation",
+            "verifiableCredential": [credential, "eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl"]
+        }))
+        .unwrap();
+        assert_eq!(
+            presentation.verifiable_credential[1],
+            PresentedCredential::Encoded("eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl".to_string())
+        );
+        assert!(presentation.validate().is_ok());
+    }
+}
@@ -0,0 +1,765 @@
+//! # WACI DIDComm
+//!
+//! The streamlined issuance and presentation flows of the WACI DIDComm interop profile. The issuer or verifier sends
+//! an out-of-band invitation with the goal code `streamlined-vc` or `streamlined-vp`, the holder answers with a
+//! proposal and the exchange continues with Issue Credential 3.0 or Present Proof 3.0. What the holder has to present
+//! is a Presentation Exchange definition, the presentations are signed with the challenge and domain of the request.
+//!
+//! Presentations and credentials are only signed and verified with `DataIntegrityProof` proofs of the
+//! `eddsa-rdfc-2022` cryptosuite. The proof suites of the profile, like `Ed25519Signature2018` of its examples, are
+//! not supported and fail with "unsupported proof", so this is not interoperable with wallets using them.
+//! <https://identity.foundation/waci-didcomm/>
+
+use crate::credentialmanifest::{CredentialApplication, CredentialManifest, CONTEXT_FULFILLMENT};
+use crate::dataintegrity::{DataIntegrityIssuer, DataIntegrityVerifier};
+use crate::error::ProtocolError;
+use crate::issuecredential::formats::{
+    CREDENTIAL_APPLICATION, CREDENTIAL_FULFILLMENT, CREDENTIAL_MANIFEST,
+};
+use crate::issuecredentialv3::{IssueCredentialV3, IssueCredentialV3ResponseBuilder};
+use crate::presentationexchange::{PresentationDefinition, PresentationSubmission};
+use crate::presentproof::formats::{DIF_PRESENTATION_DEFINITION, DIF_PRESENTATION_SUBMISSION};
+use crate::presentproofv3::{PresentProofV3, PresentProofV3ResponseBuilder};
+use crate::verifiablecredential::{DataModelVersion, VerifiableCredential, VerifiablePresentation};
+use crate::AckBuilder;
+use didcomm_rs::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const STREAMLINED_VC: &str = "streamlined-vc";
+pub const STREAMLINED_VP: &str = "streamlined-vp";
+
+/// Format of the credentials in descriptor maps, credentials are secured with data integrity proofs.
+const LDP_VC: &str = "ldp_vc";
+
+/// Challenge and domain the holder signs its presentation with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Options {
+    pub challenge: String,
+    pub domain: String,
+}
+
+impl Options {
+    /// Options with a random challenge.
+    pub fn new(domain: &str) -> Self {
+        Options {
+            challenge: Uuid::new_v4().to_string(),
+            domain: domain.to_string(),
+        }
+    }
+}
+
+/// Parses a message as WACI DIDComm sends it, with JSON objects as attachment data.
+pub fn parse(message: &str) -> Result<Message, ProtocolError> {
+    let mut fields: Value = serde_json::from_str(message).map_err(|_| "invalid message")?;
+    if let Some(attachments) = fields.get_mut("attachments").and_then(Value::as_array_mut) {
+        for attachment in attachments {
+            if let Some(data) = attachment.pointer_mut("/data/json") {
+                if !data.is_string() {
+                    *data = json!(data.to_string());
+                }
+            }
+        }
+    }
+    Ok(serde_json::from_value(fields).map_err(|_| "invalid message")?)
+}
+
+/// Holder: answers an invitation with propose-credential or propose-presentation, by its goal code.
+pub fn propose(invitation: &Message, did: &str) -> Result<Message, ProtocolError> {
+    let header = invitation.get_didcomm_header();
+    if header.m_type != "https://didcomm.org/out-of-band/2.0/invitation" {
+        return Err("unsupported message".into());
+    }
+    let body: Value = invitation
+        .get_body()
+        .ok()
+        .and_then(|body| serde_json::from_str(&body).ok())
+        .unwrap_or_default();
+    let proposal = match body["goal_code"].as_str() {
+        Some(STREAMLINED_VC) => {
+            IssueCredentialV3ResponseBuilder::new().build_propose_credential()?
+        }
+        Some(STREAMLINED_VP) => {
+            PresentProofV3ResponseBuilder::new().build_propose_presentation()?
+        }
+        _ => return Err("unsupported goal code".into()),
+    };
+    let inviter = header.from.as_ref().ok_or("missing from")?;
+    Ok(proposal.pthid(&header.id).from(did).to(&[inviter]))
+}
+
+/// Verifier: requests a presentation satisfying the definition for a propose-presentation.
+pub fn request_presentation(
+    proposal: &Message,
+    definition: &PresentationDefinition,
+    options: &Options,
+) -> Result<Message, ProtocolError> {
+    let request = PresentProofV3ResponseBuilder::new()
+        .message(proposal.clone())
+        .will_confirm(true)
+        .attachment(
+            DIF_PRESENTATION_DEFINITION,
+            json!({ "options": options, "presentation_definition": definition }),
+        )
+        .build_request_presentation()?;
+    Ok(reply(proposal, request))
+}
+
+/// Holder: presents the credentials the request asks for, signed by the holder.
+pub fn present(
+    request: &Message,
+    credentials: &[VerifiableCredential],
+    holder: &DataIntegrityIssuer,
+) -> Result<Message, ProtocolError> {
+    let (definition, options) = presentation_request(request)?;
+    let mut presentation = submit(&definition, credentials, &holder.did())?;
+    presentation = holder.sign_presentation(&presentation, &options.challenge, &options.domain)?;
+    let response = PresentProofV3ResponseBuilder::new()
+        .message(request.clone())
+        .presentation(DIF_PRESENTATION_SUBMISSION, presentation)
+        .build_presentation()?;
+    Ok(reply(request, response))
+}
+
+/// Verifier: verifies the presentation answers the request, returns it to read the presented claims. The sender of
+/// the message has to be the holder of the presentation and the subject of the presented credentials.
+pub fn verify_presentation(
+    request: &Message,
+    presentation: &Message,
+    verifier: &DataIntegrityVerifier,
+) -> Result<VerifiablePresentation, ProtocolError> {
+    let (definition, options) = presentation_request(request)?;
+    let from = presentation.get_didcomm_header().from.clone();
+    let received = PresentProofV3::try_from(presentation)?;
+    if received.thid != PresentProofV3::try_from(request)?.thid {
+        return Err("invalid thread".into());
+    }
+    let presentation: VerifiablePresentation =
+        attachment(&received.attachments, DIF_PRESENTATION_SUBMISSION)?;
+    verify_signed(&presentation, &options, verifier, from.as_deref())?;
+    let document = json!(presentation);
+    PresentationSubmission::from_presentation(&document)?.verify(&definition, &document)?;
+    Ok(presentation)
+}
+
+/// Issuer: offers the credentials of the manifest for a propose-credential. The holder has to present the
+/// credentials of the `presentation_definition` of the manifest, if it has one.
+pub fn offer_credential(
+    proposal: &Message,
+    credential_manifest: &CredentialManifest,
+    options: &Options,
+) -> Result<Message, ProtocolError> {
+    let offer = IssueCredentialV3ResponseBuilder::new()
+        .message(proposal.clone())
+        .attachment(
+            CREDENTIAL_MANIFEST,
+            json!({ "options": options, "credential_manifest": credential_manifest }),
+        )
+        .build_offer_credential()?;
+    Ok(reply(proposal, offer))
+}
+
+/// Holder: applies for the offered credentials, presenting the credentials the manifest asks for.
+pub fn request_credential(
+    offer: &Message,
+    credentials: &[VerifiableCredential],
+    holder: &DataIntegrityIssuer,
+) -> Result<Message, ProtocolError> {
+    let (manifest, options) = credential_offer(offer)?;
+    let mut application = match manifest.presentation_definition.as_ref() {
+        Some(definition) => submit(definition, credentials, &holder.did())?,
+        None => VerifiablePresentation::new(DataModelVersion::V2_0, Some(&holder.did()), vec![]),
+    };
+    CredentialApplication {
+        id: Uuid::new_v4().to_string(),
+        spec_version: manifest.spec_version.clone(),
+        manifest_id: manifest.id.to_string(),
+        format: json!({ "ldp_vp": { "proof_type": ["DataIntegrityProof"] } }),
+    }
+    .embed(&mut application);
+    application = holder.sign_presentation(&application, &options.challenge, &options.domain)?;
+    let request = IssueCredentialV3ResponseBuilder::new()
+        .message(offer.clone())
+        .attachment(CREDENTIAL_APPLICATION, json!(application))
+        .build_request_credential()?;
+    Ok(reply(offer, request))
+}
+
+/// Issuer: verifies the credential application of the request answers the offer, the sender of the request has to be
+/// the holder of the application.
+pub fn verify_application(
+    offer: &Message,
+    request: &Message,
+    verifier: &DataIntegrityVerifier,
+) -> Result<VerifiablePresentation, ProtocolError> {
+    let (manifest, options) = credential_offer(offer)?;
+    let received = IssueCredentialV3::try_from(request)?;
+    if received.thid != IssueCredentialV3::try_from(offer)?.thid {
+        return Err("invalid thread".into());
+    }
+    let application: VerifiablePresentation =
+        attachment(&received.attachments, CREDENTIAL_APPLICATION)?;
+    let from = request.get_didcomm_header().from.as_deref();
+    verify_signed(&application, &options, verifier, from)?;
+    manifest.check_application(&json!(application))?;
+    Ok(application)
+}
+
+/// Issuer: issues the signed credentials for the output descriptors of the manifest, in their order.
+pub fn issue_credential(
+    offer: &Message,
+    request: &Message,
+    credentials: Vec<VerifiableCredential>,
+    issuer: &DataIntegrityIssuer,
+) -> Result<Message, ProtocolError> {
+    let (manifest, options) = credential_offer(offer)?;
+    if manifest.output_descriptors.len() != credentials.len() {
+        return Err("invalid credentials".into());
+    }
+    let response = manifest.respond(None, LDP_VC);
+    let mut fulfillment =
+        VerifiablePresentation::new(DataModelVersion::V2_0, Some(&issuer.did()), credentials);
+    // WACI DIDComm 1.0 predates the renaming of fulfillments to credential responses
+    fulfillment.context.push(json!(CONTEXT_FULFILLMENT));
+    fulfillment.properties.insert(
+        "credential_fulfillment".to_string(),
+        json!({
+            "id": response.id,
+            "manifest_id": response.manifest_id,
+            "descriptor_map": response.fulfillment.map(|fulfillment| fulfillment.descriptor_map),
+        }),
+    );
+    let fulfillment =
+        issuer.sign_presentation(&fulfillment, &options.challenge, &options.domain)?;
+    let issue = IssueCredentialV3ResponseBuilder::new()
+        .message(request.clone())
+        .attachment(CREDENTIAL_FULFILLMENT, json!(fulfillment))
+        .build_issue_credential()?;
+    Ok(reply(request, issue))
+}
+
+/// Holder: verifies the fulfillment of an issue-credential answers the offer and returns the issued credentials. The
+/// fulfillment and the credentials have to be signed by the issuer of the manifest, for the options of the offer.
+pub fn receive_credentials(
+    offer: &Message,
+    issue: &Message,
+    verifier: &DataIntegrityVerifier,
+) -> Result<Vec<VerifiableCredential>, ProtocolError> {
+    let (manifest, options) = credential_offer(offer)?;
+    let received = IssueCredentialV3::try_from(issue)?;
+    if received.thid != IssueCredentialV3::try_from(offer)?.thid {
+        return Err("invalid thread".into());
+    }
+    let fulfillment: VerifiablePresentation =
+        attachment(&received.attachments, CREDENTIAL_FULFILLMENT)?;
+    let manifest_id = fulfillment
+        .properties
+        .get("credential_fulfillment")
+        .and_then(|fulfillment| fulfillment["manifest_id"].as_str());
+    if manifest_id != Some(manifest.id.as_str()) {
+        return Err("invalid manifest".into());
+    }
+    let issuer = did_of(&manifest.issuer.id);
+    let signer = fulfillment
+        .proof
+        .as_ref()
+        .and_then(|proof| proof["verificationMethod"].as_str())
+        .map(did_of);
+    if signer != Some(issuer)
+        || fulfillment
+            .embedded_credentials()
+            .any(|credential| did_of(credential.issuer.id()) != issuer)
+    {
+        return Err("invalid issuer".into());
+    }
+    verifier.verify_presentation(&fulfillment, &options.challenge, &options.domain)?;
+    Ok(fulfillment.embedded_credentials().cloned().collect())
+}
+
+/// Acknowledges the presentation or the issued credentials, completing the flow.
+pub fn ack(message: &Message) -> Result<Message, ProtocolError> {
+    let ack = AckBuilder::new().message(message.clone()).build()?;
+    Ok(reply(message, ack))
+}
+
+/// Picks the credentials satisfying the definition and embeds the submission in an unsigned presentation.
+fn submit(
+    definition: &PresentationDefinition,
+    credentials: &[VerifiableCredential],
+    holder: &str,
+) -> Result<VerifiablePresentation, &'static str> {
+    let documents: Vec<Value> = credentials
+        .iter()
+        .map(|credential| json!(credential))
+        .collect();
+    let (submission, selected) = definition.submit(&documents, LDP_VC)?;
+    let selected: Vec<VerifiableCredential> = selected
+        .into_iter()
+        .map(|credential| serde_json::from_value(credential).map_err(|_| "invalid credential"))
+        .collect::<Result<_, _>>()?;
+    let version = match selected.first() {
+        Some(credential) => credential.version()?,
+        None => DataModelVersion::V2_0,
+    };
+    let mut presentation = VerifiablePresentation::new(version, Some(holder), selected);
+    submission.embed(&mut presentation);
+    Ok(presentation)
+}
+
+/// Verifies the presentation is signed for the options by its holder, who sent it and is the subject of all its
+/// credentials.
+fn verify_signed(
+    presentation: &VerifiablePresentation,
+    options: &Options,
+    verifier: &DataIntegrityVerifier,
+    from: Option<&str>,
+) -> Result<(), &'static str> {
+    verifier.verify_presentation(presentation, &options.challenge, &options.domain)?;
+    let holder = presentation.holder.as_deref().ok_or("invalid holder")?;
+    if from != Some(holder) {
+        return Err("invalid holder");
+    }
+    for credential in presentation.embedded_credentials() {
+        let subjects = match &credential.credential_subject {
+            Value::Array(subjects) => subjects.iter().collect(),
+            subject => vec![subject],
+        };
+        if subjects
+            .iter()
+            .any(|subject| subject["id"].as_str() != Some(holder))
+        {
+            return Err("invalid holder");
+        }
+    }
+    Ok(())
+}
+
+/// The DID of a DID URL like `did:example:123?linked-domains=3` or `did:example:123#key-1`.
+fn did_of(did_url: &str) -> &str {
+    did_url.split(['?', '#']).next().unwrap_or_default()
+}
+
+fn presentation_request(
+    request: &Message,
+) -> Result<(PresentationDefinition, Options), &'static str> {
+    let request = PresentProofV3::try_from(request)?;
+    let data: Value = attachment(&request.attachments, DIF_PRESENTATION_DEFINITION)?;
+    Ok((
+        serde_json::from_value(data["presentation_definition"].clone())
+            .map_err(|_| "invalid definition")?,
+        serde_json::from_value(data["options"].clone()).map_err(|_| "missing options")?,
+    ))
+}
+
+fn credential_offer(offer: &Message) -> Result<(CredentialManifest, Options), &'static str> {
+    let data: Value = attachment(
+        &IssueCredentialV3::try_from(offer)?.attachments,
+        CREDENTIAL_MANIFEST,
+    )?;
+    Ok((
+        CredentialManifest::from_offer(offer)?,
+        serde_json::from_value(data["options"].clone()).map_err(|_| "missing options")?,
+    ))
+}
+
+/// The first attachment in the format.
+fn attachment<T: serde::de::DeserializeOwned>(
+    attachments: &[(Option<String>, Vec<u8>)],
+    format: &str,
+) -> Result<T, &'static str> {
+    let (_, data) = attachments
+        .iter()
+        .find(|(attachment_format, _)| attachment_format.as_deref() == Some(format))
+        .ok_or("missing attachment")?;
+    serde_json::from_slice(data).map_err(|_| "invalid attachment")
+}
+
+/// Addresses the reply to the sender of the received message.
+fn reply(received: &Message, mut message: Message) -> Message {
+    let header = received.get_didcomm_header();
+    if let Some(from) = header.from.as_ref() {
+        message = message.to(&[from]);
+    }
+    if let Some(to) = header.to.first() {
+        message = message.from(to);
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invitation::{GoalCode, InvitationBuilder};
+    use crate::presentproofv3::PRESENTATION;
+    use did_key::{generate, Ed25519KeyPair};
+
+    /// The request-presentation example of the WACI DIDComm specification.
+    const REQUEST_PRESENTATION: &str = r#"{
+        "type": "https://didcomm.org/present-proof/3.0/request-presentation",
+        "id": "0ac534c8-98ed-4fe3-8a41-3600775e1e92",
+        "thid": "95e63a5f-73e1-46ac-b269-48bb22591bfa",
+        "from": "did:example:verifier",
+        "to": ["did:example:prover"],
+        "body": { "will_confirm": true },
+        "attachments": [{
+            "id": "ed7d9b1f-9eed-4bde-b81c-3aa7485cf947",
+            "media_type": "application/json",
+            "format": "dif/presentation-exchange/definitions@v1.0",
+            "data": {
+                "json": {
+                    "options": {
+                        "challenge": "23516943-1d79-4ebd-8981-623f036365ef",
+                        "domain": "us.gov/DriversLicense"
+                    },
+                    "presentation_definition": {
+                        "id": "32f54163-7166-48f1-93d8-ff217bdb0654",
+                        "input_descriptors": [{
+                            "id": "citizenship_input_1",
+                            "name": "US Passport",
+                            "group": ["A"],
+                            "schema": [{ "uri": "https://w3id.org/citizenship/v1" }],
+                            "constraints": {
+                                "fields": [{
+                                    "path": ["$.credentialSubject.birthDate", "$.vc.credentialSubject.birthDate"],
+                                    "filter": { "type": "string", "format": "date" }
+                                }]
+                            }
+                        }]
+                    }
+                }
+            }
+        }]
+    }"#;
+
+    /// The streamlined-vc examples of the WACI DIDComm specification, abridged.
+    const PROPOSE_CREDENTIAL: &str = r#"{
+        "type": "https://didcomm.org/issue-credential/3.0/propose-credential",
+        "id": "7f62f655-9cac-4728-854a-775ba6944593",
+        "pthid": "599f3638-b563-4937-9487-dfe55099d900",
+        "from": "did:example:receiver",
+        "to": ["did:example:sender"],
+        "body": {}
+    }"#;
+
+    const OFFER_CREDENTIAL: &str = r#"{
+        "type": "https://didcomm.org/issue-credential/3.0/offer-credential",
+        "id": "07c44208-06a8-4ca8-9b2b-5c7a5f1dcd8b",
+        "thid": "7f62f655-9cac-4728-854a-775ba6944593",
+        "from": "did:example:sender",
+        "to": ["did:example:receiver"],
+        "body": {},
+        "attachments": [{
+            "id": "e00e11d4-906d-4c88-ba72-7c66c7113a78",
+            "media_type": "application/json",
+            "format": "dif/credential-manifest/manifest@v1.0",
+            "data": {
+                "json": {
+                    "options": {
+                        "challenge": "508adef4-b8e0-4edf-a53d-a260371c1423",
+                        "domain": "9rf25a28rs96"
+                    },
+                    "credential_manifest": {
+                        "id": "dcc75a16-19f5-4273-84ce-4da69ee2b7fe",
+                        "version": "0.1.0",
+                        "issuer": {
+                            "id": "did:example:123?linked-domains=3",
+                            "name": "Washington State Government"
+                        },
+                        "output_descriptors": [{
+                            "id": "driver_license_output",
+                            "schema": "https://schema.org/EducationalOccupationalCredential",
+                            "display": { "title": { "path": ["$.name", "$.vc.name"], "fallback": "Washington State Driver License" } }
+                        }],
+                        "presentation_definition": {
+                            "id": "8246867e-fdce-48de-a825-9d84ec16c6c9",
+                            "input_descriptors": [{
+                                "id": "prc_input",
+                                "name": "Permanent Resident Card",
+                                "purpose": "We need your PRC to verify your status.",
+                                "schema": [{ "uri": "https://w3id.org/citizenship#PermanentResidentCard" }],
+                                "constraints": {
+                                    "fields": [{
+                                        "path": ["$.credentialSubject.givenName"],
+                                        "filter": { "type": "string" }
+                                    }]
+                                }
+                            }]
+                        }
+                    }
+                }
+            }
+        }]
+    }"#;
+
+    const REQUEST_CREDENTIAL: &str = r#"{
+        "type": "https://didcomm.org/issue-credential/3.0/request-credential",
+        "id": "c6686159-ef49-45b2-938f-51818da14723",
+        "thid": "7f62f655-9cac-4728-854a-775ba6944593",
+        "from": "did:example:receiver",
+        "to": ["did:example:sender"],
+        "body": {},
+        "attachments": [{
+            "id": "e00e11d4-906d-4c88-ba72-7c66c7113a78",
+            "media_type": "application/json",
+            "format": "dif/credential-manifest/application@v1.0",
+            "data": {
+                "json": {
+                    "@context": [
+                        "https://www.w3.org/2018/credentials/v1",
+                        "https://identity.foundation/credential-manifest/application/v1",
+                        "https://identity.foundation/presentation-exchange/submission/v1"
+                    ],
+                    "type": ["VerifiablePresentation", "CredentialApplication", "PresentationSubmission"],
+                    "holder": "did:example:receiver",
+                    "credential_application": {
+                        "id": "9b1deb4d-3b7d-4bad-9bdd-2b0d7b3dcb6d",
+                        "manifest_id": "dcc75a16-19f5-4273-84ce-4da69ee2b7fe",
+                        "format": { "ldp_vp": { "proof_type": ["Ed25519Signature2018"] } }
+                    },
+                    "presentation_submission": {
+                        "id": "2a1ecbc6-9f3b-4e22-b0be-c3a45bd3c0cd",
+                        "definition_id": "8246867e-fdce-48de-a825-9d84ec16c6c9",
+                        "descriptor_map": [{ "id": "prc_input", "format": "ldp_vc", "path": "$.verifiableCredential[0]" }]
+                    },
+                    "verifiableCredential": [{
+                        "@context": ["https://www.w3.org/2018/credentials/v1", "https://w3id.org/citizenship/v1"],
+                        "id": "https://issuer.oidp.uscis.gov/credentials/83627465",
+                        "type": ["VerifiableCredential", "PermanentResidentCard"],
+                        "issuer": "did:example:28394728934792387",
+                        "issuanceDate": "2019-12-03T12:19:52Z",
+                        "credentialSubject": {
+                            "id": "did:example:receiver",
+                            "type": ["PermanentResident", "Person"],
+                            "givenName": "JOHN",
+                            "familyName": "SMITH"
+                        },
+                        "proof": {
+                            "type": "Ed25519Signature2018",
+                            "created": "2020-01-30T03:32:57Z",
+                            "proofPurpose": "assertionMethod",
+                            "verificationMethod": "did:example:28394728934792387#keys-7f83he7s8",
+                            "jws": "eyJhbGciOiJFZERTQSIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..zX7XMIeHSz"
+                        }
+                    }],
+                    "proof": {
+                        "type": "Ed25519Signature2018",
+                        "verificationMethod": "did:example:receiver#key-1",
+                        "created": "2021-05-14T20:16:29Z",
+                        "proofPurpose": "authentication",
+                        "challenge": "508adef4-b8e0-4edf-a53d-a260371c1423",
+                        "domain": "9rf25a28rs96",
+                        "jws": "eyJhbGciOiAiRWREU0EiLCAiYjY0IjogZmFsc2UsICJjcml0IjogWyJiNjQiXX0..7M9LwdJR1"
+                    }
+                }
+            }
+        }]
+    }"#;
+
+    const ISSUE_CREDENTIAL: &str = r#"{
+        "type": "https://didcomm.org/issue-credential/3.0/issue-credential",
+        "id": "7a476bd8-cc3f-4d80-b784-caeb2ff265da",
+        "thid": "7f62f655-9cac-4728-854a-775ba6944593",
+        "from": "did:example:sender",
+        "to": ["did:example:receiver"],
+        "body": {},
+        "attachments": [{
+            "id": "2a3f1c4c-623c-44e6-b159-179048c51260",
+            "media_type": "application/ld+json",
+            "format": "dif/credential-manifest/fulfillment@v1.0",
+            "data": {
+                "json": {
+                    "@context": [
+                        "https://www.w3.org/2018/credentials/v1",
+                        "https://identity.foundation/credential-manifest/fulfillment/v1"
+                    ],
+                    "type": ["VerifiablePresentation", "CredentialFulfillment"],
+                    "holder": "did:example:123",
+                    "credential_fulfillment": {
+                        "id": "a30e3b91-fb77-4d22-95fa-871689c322e2",
+                        "manifest_id": "dcc75a16-19f5-4273-84ce-4da69ee2b7fe",
+                        "descriptor_map": [{ "id": "driver_license_output", "format": "ldp_vc", "path": "$.verifiableCredential[0]" }]
+                    },
+                    "verifiableCredential": [{
+                        "@context": ["https://www.w3.org/2018/credentials/v1", "https://w3id.org/vaccination/v1"],
+                        "id": "https://eu.com/claims/DriversLicense",
+                        "type": ["VerifiableCredential", "EUDriversLicense"],
+                        "issuer": "did:example:123",
+                        "issuanceDate": "2010-01-01T19:23:24Z",
+                        "credentialSubject": {
+                            "id": "did:example:receiver",
+                            "license": { "number": "34DGE352", "dob": "07/13/80" }
+                        },
+                        "proof": {
+                            "type": "Ed25519Signature2018",
+                            "created": "2021-06-07T20:02:44.730614315Z",
+                            "proofPurpose": "assertionMethod",
+                            "verificationMethod": "did:example:123#key-1",
+                            "jws": "eyJhbGciOiJFZERTQSIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..sKDHIFNM8"
+                        }
+                    }],
+                    "proof": {
+                        "type": "Ed25519Signature2018",
+                        "created": "2021-06-07T20:02:44.730614315Z",
+                        "proofPurpose": "authentication",
+                        "verificationMethod": "did:example:123#key-1",
+                        "jws": "eyJhbGciOiJFZERTQSIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..Z0PuhtNc0"
+                    }
+                }
+            }
+        }]
+    }"#;
+
+    #[test]
+    fn test_streamlined_vp() {
+        let issuer = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap();
+        let holder = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder"))).unwrap();
+        let passport = issuer
+            .sign(&VerifiableCredential::new(
+                DataModelVersion::V2_0,
+                &issuer.did(),
+                json!({ "id": holder.did(), "birthDate": "1958-07-17" }),
+            ))
+            .unwrap();
+
+        let request =
+            parse(&REQUEST_PRESENTATION.replace("did:example:prover", &holder.did())).unwrap();
+        let presentation = present(&request, &[passport], &holder).unwrap();
+        let header = presentation.get_didcomm_header();
+        assert_eq!(header.m_type, PRESENTATION);
+        assert_eq!(
+            header.thid.as_deref(),
+            Some("95e63a5f-73e1-46ac-b269-48bb22591bfa")
+        );
+        assert_eq!(header.to, vec!["did:example:verifier".to_string()]);
+
+        let verifier = DataIntegrityVerifier::new();
+        let verified = verify_presentation(&request, &presentation, &verifier).unwrap();
+        assert_eq!(
+            verified
+                .embedded_credentials()
+                .next()
+                .unwrap()
+                .credential_subject["birthDate"],
+            "1958-07-17"
+        );
+        let ack = ack(&presentation).unwrap();
+        assert_eq!(
+            ack.get_didcomm_header().from.as_deref(),
+            Some("did:example:verifier")
+        );
+
+        let other = parse(&REQUEST_PRESENTATION.replace("23516943", "00000000")).unwrap();
+        assert_eq!(
+            verify_presentation(&other, &presentation, &verifier),
+            Err("invalid challenge".into())
+        );
+        let forwarded = presentation.clone().from("did:example:mallory");
+        assert_eq!(
+            verify_presentation(&request, &forwarded, &verifier),
+            Err("invalid holder".into())
+        );
+    }
+
+    #[test]
+    fn test_streamlined_vc() {
+        let issuer = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"issuer"))).unwrap();
+        let holder = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"holder"))).unwrap();
+        let verifier = DataIntegrityVerifier::new();
+        let invitation = InvitationBuilder::new()
+            .did(issuer.did())
+            .goal_code(GoalCode::StreamlinedVC)
+            .build()
+            .unwrap();
+
+        let proposal = propose(&invitation, &holder.did()).unwrap();
+        assert_eq!(
+            proposal.get_didcomm_header().pthid.as_ref(),
+            Some(&invitation.get_didcomm_header().id)
+        );
+        let manifest: CredentialManifest = serde_json::from_value(json!({
+            "id": "dcc75a16-19f5-4273-84ce-4da69ee2b7fe",
+            "issuer": { "id": issuer.did(), "name": "Washington State Government" },
+            "output_descriptors": [{ "id": "driver_license_output", "schema": "https://schema.org/EducationalOccupationalCredential" }],
+        }))
+        .unwrap();
+        let offer = offer_credential(&proposal, &manifest, &Options::new("example.com")).unwrap();
+        let request = request_credential(&offer, &[], &holder).unwrap();
+        assert!(verify_application(&offer, &request, &verifier).is_ok());
+
+        let license = issuer
+            .sign(&VerifiableCredential::new(
+                DataModelVersion::V2_0,
+                &issuer.did(),
+                json!({ "id": holder.did(), "license": { "number": "34DGE352" } }),
+            ))
+            .unwrap();
+        let issued = issue_credential(&offer, &request, vec![license.clone()], &issuer).unwrap();
+        assert_eq!(issued.get_didcomm_header().to, vec![holder.did()]);
+        assert_eq!(
+            receive_credentials(&offer, &issued, &verifier),
+            Ok(vec![license])
+        );
+
+        let forger = DataIntegrityIssuer::new(generate::<Ed25519KeyPair>(Some(b"forger"))).unwrap();
+        let forged = forger
+            .sign(&VerifiableCredential::new(
+                DataModelVersion::V2_0,
+                &forger.did(),
+                json!({ "id": holder.did() }),
+            ))
+            .unwrap();
+        let forged = issue_credential(&offer, &request, vec![forged], &issuer).unwrap();
+        assert_eq!(
+            receive_credentials(&offer, &forged, &verifier),
+            Err("invalid issuer".into())
+        );
+        let proposal = propose(&invitation, &holder.did()).unwrap();
+        let other = offer_credential(&proposal, &manifest, &Options::new("example.com")).unwrap();
+        assert_eq!(
+            receive_credentials(&other, &issued, &verifier),
+            Err("invalid thread".into())
+        );
+    }
+
+    #[test]
+    fn test_streamlined_vc_examples() {
+        let proposal = parse(PROPOSE_CREDENTIAL).unwrap();
+        let offer = parse(OFFER_CREDENTIAL).unwrap();
+        let request = parse(REQUEST_CREDENTIAL).unwrap();
+        let issued = parse(ISSUE_CREDENTIAL).unwrap();
+        for message in [&offer, &request, &issued] {
+            assert_eq!(
+                message.get_didcomm_header().thid.as_ref(),
+                Some(&proposal.get_didcomm_header().id)
+            );
+        }
+
+        let (manifest, options) = credential_offer(&offer).unwrap();
+        assert_eq!(manifest.issuer.id, "did:example:123?linked-domains=3");
+        assert_eq!(options.domain, "9rf25a28rs96");
+        let application: Value = attachment(
+            &IssueCredentialV3::try_from(&request).unwrap().attachments,
+            CREDENTIAL_APPLICATION,
+        )
+        .unwrap();
+        assert_eq!(
+            CredentialApplication::from_presentation(&application)
+                .unwrap()
+                .manifest_id,
+            manifest.id
+        );
+
+        // the examples pass the thread, manifest and issuer checks but are signed with Ed25519Signature2018, which is
+        // not supported
+        let verifier = DataIntegrityVerifier::new();
+        assert_eq!(
+            verify_application(&offer, &request, &verifier),
+            Err("unsupported proof".into())
+        );
+        assert_eq!(
+            receive_credentials(&offer, &issued, &verifier),
+            Err("unsupported proof".into())
+        );
+    }
+}